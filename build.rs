@@ -1,6 +1,11 @@
 use std::{env, path::PathBuf};
 
 fn main() {
+    // The pure Rust client talks to munged directly and needs neither libmunge nor its headers.
+    if env::var_os("CARGO_FEATURE_PURE_RUST").is_some() {
+        return;
+    }
+
     println!("cargo:rustc-link-lib=munge");
 
     let bindings = bindgen::Builder::default()
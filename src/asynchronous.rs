@@ -0,0 +1,278 @@
+use std::{
+    ffi::{CStr, CString},
+    future::Future,
+    time::Duration,
+};
+
+use crate::{
+    credential::{Credential, DecodedCredential},
+    ctx::Context,
+    enums::{Error, MungeError},
+    munge,
+    retry::retry_async,
+    sys::Metadata,
+};
+
+/// Asynchronously encodes the given message and returns a base64 encoded credential string.
+///
+/// This is the asynchronous counterpart of [`crate::encode`]. Like there, the getters of
+/// `ctx` report this call once it finished.
+///
+/// # Arguments
+///
+/// * `msg` - The message to be included with the encoded credential.
+/// * `ctx` - An optional reference to a [`Context`]. If no context is provided, defaults will be used.
+/// * `timeout` - An optional limit on the duration of the whole call.
+///
+/// # Errors
+///
+/// Returns an [`Error`] if the encoding process fails, or [`MungeError::Timeout`] if the
+/// call did not finish within `timeout`.
+///
+/// # Example
+///
+/// ```ignore
+/// let cred = encode_async("Hello, MUNGE!", None, Some(Duration::from_secs(1))).await?;
+/// ```
+pub async fn encode_async(
+    msg: &str,
+    ctx: Option<&Context>,
+    timeout: Option<Duration>,
+) -> Result<String, Error> {
+    encode_bytes_async(msg.as_bytes(), ctx, timeout).await
+}
+
+/// Asynchronously encodes the given binary payload and returns a base64 encoded credential
+/// string.
+///
+/// This is the asynchronous counterpart of [`crate::encode_bytes`].
+///
+/// # Errors
+///
+/// Returns an [`Error`] if the encoding process fails, or [`MungeError::Timeout`] if the
+/// call did not finish within `timeout`.
+pub async fn encode_bytes_async(
+    payload: &[u8],
+    ctx: Option<&Context>,
+    timeout: Option<Duration>,
+) -> Result<String, Error> {
+    munge::check_length(payload)?;
+
+    with_timeout(timeout, retry_async(ctx, || encode_inner(payload, ctx))).await
+}
+
+/// Asynchronously decodes the provided base64 encoded string.
+///
+/// This is the asynchronous counterpart of [`crate::decode`]. Like there, the getters of
+/// `ctx` report this call once it finished.
+///
+/// # Arguments
+///
+/// * `encoded_msg` - The base64 encoded credential string to decode.
+/// * `ctx` - An optional reference to a [`Context`]. If no context is provided, defaults will be used.
+/// * `timeout` - An optional limit on the duration of the whole call.
+///
+/// # Errors
+///
+/// Returns the same errors as [`crate::decode`], or [`MungeError::Timeout`] if the call
+/// did not finish within `timeout`.
+///
+/// # Example
+///
+/// ```ignore
+/// let cred = decode_async(&encoded, None, Some(Duration::from_secs(1))).await?;
+/// println!("Request from UID {}", cred.uid);
+/// ```
+pub async fn decode_async(
+    encoded_msg: &str,
+    ctx: Option<&Context>,
+    timeout: Option<Duration>,
+) -> Result<Credential, Error> {
+    Ok(Credential::try_from(
+        decode_bytes_async(encoded_msg, ctx, timeout).await?,
+    )?)
+}
+
+/// Asynchronously decodes the provided base64 encoded string into a credential with a
+/// binary payload.
+///
+/// This is the asynchronous counterpart of [`crate::decode_bytes`].
+///
+/// # Errors
+///
+/// Returns the same errors as [`crate::decode_bytes`], or [`MungeError::Timeout`] if the
+/// call did not finish within `timeout`.
+pub async fn decode_bytes_async(
+    encoded_msg: &str,
+    ctx: Option<&Context>,
+    timeout: Option<Duration>,
+) -> Result<Credential<Vec<u8>>, Error> {
+    let (cred, _) = decode_with_metadata(encoded_msg, ctx, timeout).await?;
+    Ok(cred)
+}
+
+/// Asynchronously decodes the provided base64 encoded string into a credential with a
+/// binary payload and its metadata, like [`crate::decode_full_bytes`].
+pub(crate) async fn decode_full_bytes_async(
+    encoded_msg: &str,
+    ctx: Option<&Context>,
+    timeout: Option<Duration>,
+) -> Result<DecodedCredential<Vec<u8>>, Error> {
+    let (cred, metadata) = decode_with_metadata(encoded_msg, ctx, timeout).await?;
+    munge::decoded_credential(cred, metadata)
+}
+
+async fn decode_with_metadata(
+    encoded_msg: &str,
+    ctx: Option<&Context>,
+    timeout: Option<Duration>,
+) -> Result<(Credential<Vec<u8>>, Metadata), Error> {
+    let cred: CString = CString::new(encoded_msg)?;
+
+    with_timeout(timeout, retry_async(ctx, || decode_inner(&cred, ctx))).await
+}
+
+/// Talks to munged over a non-blocking socket. Dropping the future closes the connection.
+#[cfg(feature = "pure-rust")]
+async fn encode_inner(payload: &[u8], ctx: Option<&Context>) -> Result<String, Error> {
+    let res = crate::sys::encode_async(ctx.map(|ctx| &ctx.ctx), payload).await;
+
+    munge::encode_result(res)
+}
+
+#[cfg(feature = "pure-rust")]
+async fn decode_inner(
+    cred: &CStr,
+    ctx: Option<&Context>,
+) -> Result<(Credential<Vec<u8>>, Metadata), Error> {
+    let decoded = crate::sys::decode_async(cred, ctx.map(|ctx| &ctx.ctx)).await;
+
+    munge::decode_result(decoded)
+}
+
+/// libmunge blocks, so each attempt runs on tokio's blocking pool with a copy of the context.
+///
+/// The copy replaces the context passed in once the call finished, like in the synchronous
+/// functions. Dropping the future stops waiting for the result, but the libmunge call
+/// itself runs to completion and the context is then left as it was.
+#[cfg(not(feature = "pure-rust"))]
+async fn encode_inner(payload: &[u8], ctx: Option<&Context>) -> Result<String, Error> {
+    let payload = payload.to_vec();
+    let call = ctx.cloned();
+
+    let (res, call) = blocking(move || {
+        let res = crate::sys::encode(call.as_ref().map(|ctx| &ctx.ctx), &payload);
+        (res, call)
+    })
+    .await?;
+    update(ctx, call);
+
+    munge::encode_result(res)
+}
+
+#[cfg(not(feature = "pure-rust"))]
+async fn decode_inner(
+    cred: &CStr,
+    ctx: Option<&Context>,
+) -> Result<(Credential<Vec<u8>>, Metadata), Error> {
+    let cred = cred.to_owned();
+    let call = ctx.cloned();
+
+    let (decoded, call) = blocking(move || {
+        let decoded = crate::sys::decode(&cred, call.as_ref().map(|ctx| &ctx.ctx));
+        (decoded, call)
+    })
+    .await?;
+    update(ctx, call);
+
+    munge::decode_result(decoded)
+}
+
+/// Replaces the libmunge context of the caller by the copy the call ran on.
+#[cfg(not(feature = "pure-rust"))]
+fn update(ctx: Option<&Context>, call: Option<Context>) {
+    if let (Some(ctx), Some(call)) = (ctx, call) {
+        ctx.ctx.replace(call.ctx);
+    }
+}
+
+#[cfg(not(feature = "pure-rust"))]
+async fn blocking<T, F>(f: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(res) => Ok(res),
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(_) => Err(Error::MungeError(
+            MungeError::Snafu,
+            "The blocking MUNGE call was cancelled.".to_string(),
+        )),
+    }
+}
+
+/// Fails with [`MungeError::Timeout`] if the future does not complete within `timeout`.
+async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    fut: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    let Some(timeout) = timeout else {
+        return fut.await;
+    };
+
+    tokio::time::timeout(timeout, fut)
+        .await
+        .unwrap_or_else(|_| {
+            Err(Error::MungeError(
+                MungeError::Timeout,
+                format!("No response from munged within {timeout:?}."),
+            ))
+        })
+}
+
+#[cfg(test)]
+mod async_tests {
+    use std::time::Duration;
+
+    use crate::{
+        asynchronous::{decode_async, decode_bytes_async, encode_async, encode_bytes_async},
+        ctx::Context,
+        enums::MungeZip,
+    };
+
+    #[tokio::test]
+    async fn encode_decode_async() {
+        let cred = encode_async("Hello async world!", None, None)
+            .await
+            .expect("Failed to encode");
+
+        let res = decode_async(&cred, None, Some(Duration::from_secs(5)))
+            .await
+            .expect("Failed to decode");
+        assert_eq!(res.message, "Hello async world!");
+    }
+
+    #[tokio::test]
+    async fn encode_decode_bytes_async_w_ctx() {
+        let mut ctx = Context::new();
+        ctx.set_zip(MungeZip::Zlib).expect("Failed to set zip");
+        let payload: &[u8] = &[0x00, 0x01, 0xfe, 0xff];
+
+        let cred = encode_bytes_async(payload, Some(&ctx), Some(Duration::from_secs(5)))
+            .await
+            .expect("Failed to encode");
+
+        let res = decode_bytes_async(&cred, Some(&ctx), None)
+            .await
+            .expect("Failed to decode");
+        assert_eq!(res.message, payload);
+        // The context reports the decode, whichever way it ran.
+        assert!(
+            ctx.encode_time()
+                .expect("Failed to get encode time")
+                .timestamp()
+                > 0
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use std::sync::Arc;
+#[cfg(feature = "tokio")]
+use std::{future::Future, pin::Pin, time::Duration};
+
+#[cfg(feature = "tokio")]
+use crate::asynchronous;
+use crate::{credential::DecodedCredential, ctx::Context, enums::Error, munge};
+
+/// Future returned by the asynchronous methods of [`MungeBackend`].
+#[cfg(feature = "tokio")]
+pub type BackendFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, Error>> + Send + 'a>>;
+
+/// Abstraction over the service that encodes and decodes credentials.
+///
+/// The crate-level functions always talk to munged through [`Munged`]. Code written
+/// against this trait can instead be handed an in-process implementation such as
+/// `MockBackend` (behind the `mock` feature) to test authentication paths without a
+/// running daemon.
+///
+/// Context options (TTL, cipher, MAC, zip and UID/GID restrictions) are read through
+/// the [`Context`] getters. Decoding returns the credential metadata directly instead of
+/// storing it in the context.
+///
+/// The other entry points of the crate that talk to munged, eg. [`crate::Policy`],
+/// [`crate::Challenge`], envelopes, handshakes, [`crate::FailoverContext`] and the
+/// health check, have a `_with` variant taking a backend, and the tower and tonic
+/// layers accept one with `with_backend`.
+pub trait MungeBackend: Send + Sync {
+    /// Encodes the given binary payload and returns a credential string.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`Error`] if the encoding process fails.
+    fn encode_bytes(&self, payload: &[u8], ctx: Option<&Context>) -> Result<String, Error>;
+
+    /// Decodes the credential string into a credential with a binary payload and its
+    /// metadata.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`Error`] if decoding fails. Expired, rewound and replayed credentials
+    /// are reported as [`Error::CredentialRejected`].
+    fn decode_bytes(
+        &self,
+        encoded_msg: &str,
+        ctx: Option<&Context>,
+    ) -> Result<DecodedCredential<Vec<u8>>, Error>;
+
+    /// Encodes the given message and returns a credential string.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`Error`] if the encoding process fails.
+    fn encode(&self, msg: &str, ctx: Option<&Context>) -> Result<String, Error> {
+        self.encode_bytes(msg.as_bytes(), ctx)
+    }
+
+    /// Decodes the credential string into a credential with a UTF-8 message and its
+    /// metadata.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`Error`] if decoding fails or the payload is not valid UTF-8.
+    fn decode(&self, encoded_msg: &str, ctx: Option<&Context>) -> Result<DecodedCredential, Error> {
+        Ok(DecodedCredential::try_from(
+            self.decode_bytes(encoded_msg, ctx)?,
+        )?)
+    }
+
+    /// Asynchronously encodes the given binary payload and returns a credential string.
+    ///
+    /// The default implementation calls [`MungeBackend::encode_bytes`] when polled and
+    /// ignores the timeout, which suits in-process backends that do not block.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`Error`] if the encoding process fails, or
+    /// [`crate::MungeError::Timeout`] if the call did not finish within `timeout`.
+    #[cfg(feature = "tokio")]
+    fn encode_bytes_async<'a>(
+        &'a self,
+        payload: &'a [u8],
+        ctx: Option<&'a Context>,
+        timeout: Option<Duration>,
+    ) -> BackendFuture<'a, String> {
+        let _ = timeout;
+        Box::pin(async move { self.encode_bytes(payload, ctx) })
+    }
+
+    /// Asynchronously decodes the credential string into a credential with a binary
+    /// payload and its metadata.
+    ///
+    /// The default implementation calls [`MungeBackend::decode_bytes`] when polled and
+    /// ignores the timeout, which suits in-process backends that do not block.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`MungeBackend::decode_bytes`], or
+    /// [`crate::MungeError::Timeout`] if the call did not finish within `timeout`.
+    #[cfg(feature = "tokio")]
+    fn decode_bytes_async<'a>(
+        &'a self,
+        encoded_msg: &'a str,
+        ctx: Option<&'a Context>,
+        timeout: Option<Duration>,
+    ) -> BackendFuture<'a, DecodedCredential<Vec<u8>>> {
+        let _ = timeout;
+        Box::pin(async move { self.decode_bytes(encoded_msg, ctx) })
+    }
+}
+
+/// Backend talking to the munged daemon.
+///
+/// This goes through libmunge, or through the native socket client when the
+/// `pure-rust` feature is enabled, exactly like [`crate::encode`] and [`crate::decode`].
+/// The asynchronous methods behave like [`crate::encode_bytes_async`] and
+/// [`crate::decode_bytes_async`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Munged;
+
+impl MungeBackend for Munged {
+    fn encode_bytes(&self, payload: &[u8], ctx: Option<&Context>) -> Result<String, Error> {
+        munge::encode_bytes(payload, ctx)
+    }
+
+    fn decode_bytes(
+        &self,
+        encoded_msg: &str,
+        ctx: Option<&Context>,
+    ) -> Result<DecodedCredential<Vec<u8>>, Error> {
+        munge::decode_full_bytes(encoded_msg, ctx)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn encode_bytes_async<'a>(
+        &'a self,
+        payload: &'a [u8],
+        ctx: Option<&'a Context>,
+        timeout: Option<Duration>,
+    ) -> BackendFuture<'a, String> {
+        Box::pin(asynchronous::encode_bytes_async(payload, ctx, timeout))
+    }
+
+    #[cfg(feature = "tokio")]
+    fn decode_bytes_async<'a>(
+        &'a self,
+        encoded_msg: &'a str,
+        ctx: Option<&'a Context>,
+        timeout: Option<Duration>,
+    ) -> BackendFuture<'a, DecodedCredential<Vec<u8>>> {
+        Box::pin(asynchronous::decode_full_bytes_async(
+            encoded_msg,
+            ctx,
+            timeout,
+        ))
+    }
+}
+
+impl<B: MungeBackend + ?Sized> MungeBackend for &B {
+    fn encode_bytes(&self, payload: &[u8], ctx: Option<&Context>) -> Result<String, Error> {
+        (**self).encode_bytes(payload, ctx)
+    }
+
+    fn decode_bytes(
+        &self,
+        encoded_msg: &str,
+        ctx: Option<&Context>,
+    ) -> Result<DecodedCredential<Vec<u8>>, Error> {
+        (**self).decode_bytes(encoded_msg, ctx)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn encode_bytes_async<'a>(
+        &'a self,
+        payload: &'a [u8],
+        ctx: Option<&'a Context>,
+        timeout: Option<Duration>,
+    ) -> BackendFuture<'a, String> {
+        (**self).encode_bytes_async(payload, ctx, timeout)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn decode_bytes_async<'a>(
+        &'a self,
+        encoded_msg: &'a str,
+        ctx: Option<&'a Context>,
+        timeout: Option<Duration>,
+    ) -> BackendFuture<'a, DecodedCredential<Vec<u8>>> {
+        (**self).decode_bytes_async(encoded_msg, ctx, timeout)
+    }
+}
+
+impl<B: MungeBackend + ?Sized> MungeBackend for Box<B> {
+    fn encode_bytes(&self, payload: &[u8], ctx: Option<&Context>) -> Result<String, Error> {
+        (**self).encode_bytes(payload, ctx)
+    }
+
+    fn decode_bytes(
+        &self,
+        encoded_msg: &str,
+        ctx: Option<&Context>,
+    ) -> Result<DecodedCredential<Vec<u8>>, Error> {
+        (**self).decode_bytes(encoded_msg, ctx)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn encode_bytes_async<'a>(
+        &'a self,
+        payload: &'a [u8],
+        ctx: Option<&'a Context>,
+        timeout: Option<Duration>,
+    ) -> BackendFuture<'a, String> {
+        (**self).encode_bytes_async(payload, ctx, timeout)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn decode_bytes_async<'a>(
+        &'a self,
+        encoded_msg: &'a str,
+        ctx: Option<&'a Context>,
+        timeout: Option<Duration>,
+    ) -> BackendFuture<'a, DecodedCredential<Vec<u8>>> {
+        (**self).decode_bytes_async(encoded_msg, ctx, timeout)
+    }
+}
+
+impl<B: MungeBackend + ?Sized> MungeBackend for Arc<B> {
+    fn encode_bytes(&self, payload: &[u8], ctx: Option<&Context>) -> Result<String, Error> {
+        (**self).encode_bytes(payload, ctx)
+    }
+
+    fn decode_bytes(
+        &self,
+        encoded_msg: &str,
+        ctx: Option<&Context>,
+    ) -> Result<DecodedCredential<Vec<u8>>, Error> {
+        (**self).decode_bytes(encoded_msg, ctx)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn encode_bytes_async<'a>(
+        &'a self,
+        payload: &'a [u8],
+        ctx: Option<&'a Context>,
+        timeout: Option<Duration>,
+    ) -> BackendFuture<'a, String> {
+        (**self).encode_bytes_async(payload, ctx, timeout)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn decode_bytes_async<'a>(
+        &'a self,
+        encoded_msg: &'a str,
+        ctx: Option<&'a Context>,
+        timeout: Option<Duration>,
+    ) -> BackendFuture<'a, DecodedCredential<Vec<u8>>> {
+        (**self).decode_bytes_async(encoded_msg, ctx, timeout)
+    }
+}
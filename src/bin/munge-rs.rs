@@ -0,0 +1,608 @@
+//! Command-line counterpart of the MUNGE `munge`, `unmunge` and `remunge` tools.
+
+use std::{
+    ffi::{CStr, CString},
+    fmt::Display,
+    fs::File,
+    io::{self, Read, Write},
+    net::Ipv4Addr,
+    num::ParseIntError,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local, Utc};
+use clap::{Args, Parser, Subcommand};
+use munge_rs::{
+    decode_full_bytes, encode_bytes, Context, DecodedCredential, Error, MungeCipher, MungeError,
+    MungeMac, MungeZip,
+};
+
+type Result<T, E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
+
+/// Metadata keys printed by `decode`, in output order.
+const KEYS: &[&str] = &[
+    "STATUS",
+    "ENCODE_HOST",
+    "ENCODE_TIME",
+    "DECODE_TIME",
+    "TTL",
+    "CIPHER",
+    "MAC",
+    "ZIP",
+    "UID",
+    "GID",
+    "UID_RESTRICTION",
+    "GID_RESTRICTION",
+    "LENGTH",
+];
+
+#[derive(Parser)]
+#[command(name = "munge-rs", version, about = "MUNGE Uid 'N' Gid Emporium tools")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a credential, like `munge`.
+    Encode(EncodeArgs),
+    /// Validate a credential and print its metadata, like `unmunge`.
+    Decode(DecodeArgs),
+    /// Benchmark credential encoding and decoding, like `remunge`.
+    Bench(BenchArgs),
+}
+
+/// Options applied to the MUNGE context.
+#[derive(Args)]
+struct CtxArgs {
+    /// Cipher type (name or number).
+    #[arg(short, long, value_parser = parse_cipher)]
+    cipher: Option<MungeCipher>,
+    /// MAC type (name or number).
+    #[arg(short, long, value_parser = parse_mac)]
+    mac: Option<MungeMac>,
+    /// Compression type (name or number).
+    #[arg(short, long, value_parser = parse_zip)]
+    zip: Option<MungeZip>,
+    /// Time-to-live in seconds (0 for the default, -1 for the maximum).
+    #[arg(short, long, allow_negative_numbers = true, value_parser = parse_ttl)]
+    ttl: Option<u32>,
+    /// Restrict decoding to this user (name or UID).
+    #[arg(short = 'u', long, value_parser = parse_uid)]
+    restrict_uid: Option<u32>,
+    /// Restrict decoding to this group (name or GID).
+    #[arg(short = 'g', long, value_parser = parse_gid)]
+    restrict_gid: Option<u32>,
+    /// Local socket of munged.
+    #[arg(short = 'S', long)]
+    socket: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct EncodeArgs {
+    #[command(flatten)]
+    ctx: CtxArgs,
+    /// Encode an empty payload instead of reading the input.
+    #[arg(short, long, conflicts_with_all = ["string", "input"])]
+    no_input: bool,
+    /// Use the string as payload.
+    #[arg(short, long, conflicts_with = "input")]
+    string: Option<String>,
+    /// Read the payload from the file ("-" for stdin).
+    #[arg(short, long)]
+    input: Option<PathBuf>,
+    /// Write the credential to the file ("-" for stdout).
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// List the supported cipher types and exit.
+    #[arg(short = 'C', long)]
+    list_ciphers: bool,
+    /// List the supported MAC types and exit.
+    #[arg(short = 'M', long)]
+    list_macs: bool,
+    /// List the supported compression types and exit.
+    #[arg(short = 'Z', long)]
+    list_zips: bool,
+}
+
+#[derive(Args)]
+struct DecodeArgs {
+    /// Read the credential from the file ("-" for stdin).
+    #[arg(short, long)]
+    input: Option<PathBuf>,
+    /// Write the payload to the file ("-" for stdout).
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Write the metadata to the file ("-" for stdout), instead of before the payload.
+    #[arg(short, long)]
+    metadata: Option<PathBuf>,
+    /// Only print these comma-separated metadata keys.
+    #[arg(short, long, value_delimiter = ',', value_parser = parse_key)]
+    keys: Vec<String>,
+    /// List the metadata keys and exit.
+    #[arg(short = 'K', long)]
+    list_keys: bool,
+    /// Print numeric values only.
+    #[arg(short, long)]
+    numeric: bool,
+    /// Local socket of munged.
+    #[arg(short = 'S', long)]
+    socket: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct BenchArgs {
+    #[command(flatten)]
+    ctx: CtxArgs,
+    /// Also decode every credential.
+    #[arg(short, long)]
+    decode: bool,
+    /// Number of credentials to process.
+    #[arg(short = 'N', long, default_value_t = 1)]
+    num_creds: u64,
+    /// Run for this many seconds instead of a fixed number of credentials.
+    #[arg(short = 'D', long)]
+    duration: Option<u64>,
+    /// Length of the payload in bytes.
+    #[arg(short, long, default_value_t = 0, conflicts_with = "string")]
+    length: usize,
+    /// Use the string as payload.
+    #[arg(short, long)]
+    string: Option<String>,
+    /// Number of threads.
+    #[arg(short = 'T', long, default_value_t = 1)]
+    num_threads: usize,
+    /// Only print errors.
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let res = match cli.command {
+        Command::Encode(args) => encode(args),
+        Command::Decode(args) => decode(args),
+        Command::Bench(args) => bench(args),
+    };
+
+    match res {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("munge-rs: Error: {e}");
+            exit_code(e.as_ref())
+        }
+    }
+}
+
+fn encode(args: EncodeArgs) -> Result<ExitCode> {
+    if args.list_ciphers || args.list_macs || args.list_zips {
+        if args.list_ciphers {
+            list_types("Cipher", MungeCipher::supported().map(|c| (c, c as u32)));
+        }
+        if args.list_macs {
+            list_types("MAC", MungeMac::supported().map(|m| (m, m as u32)));
+        }
+        if args.list_zips {
+            list_types("Compression", MungeZip::supported().map(|z| (z, z as u32)));
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let ctx = args.ctx.context()?;
+
+    let payload = if args.no_input {
+        Vec::new()
+    } else if let Some(string) = args.string {
+        string.into_bytes()
+    } else {
+        read_input(args.input.as_deref())?
+    };
+
+    let cred = encode_bytes(&payload, Some(&ctx))?;
+
+    let mut out = open_output(args.output.as_deref())?;
+    writeln!(out, "{cred}")?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn decode(args: DecodeArgs) -> Result<ExitCode> {
+    if args.list_keys {
+        KEYS.iter().for_each(|key| println!("{key}"));
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let mut ctx = Context::new();
+    if let Some(socket) = args.socket {
+        ctx.set_socket(socket)?;
+    }
+
+    let input = read_input(args.input.as_deref())?;
+    let encoded = String::from_utf8_lossy(&input);
+
+    // Rejected credentials are still reported, together with the failure.
+    let (status, cred) = match decode_full_bytes(encoded.trim(), Some(&ctx)) {
+        Ok(cred) => (None, cred),
+        Err(Error::CredentialRejected(e, _, cred)) => (Some(e), cred),
+        Err(e) => return Err(e.into()),
+    };
+    let restrictions = (ctx.uid_restriction()?, ctx.gid_restriction()?);
+
+    let metadata = metadata(status, &cred, restrictions, args.numeric);
+    let width = metadata.iter().map(|(key, _)| key.len()).max().unwrap_or(0) + 2;
+    let mut lines = String::new();
+    for (key, value) in metadata {
+        if args.keys.is_empty() || args.keys.iter().any(|k| k == key) {
+            lines.push_str(&format!("{:<width$}{value}\n", format!("{key}:")));
+        }
+    }
+
+    let mut out = open_output(args.output.as_deref())?;
+    match args.metadata.as_deref() {
+        Some(path) => open_output(Some(path))?.write_all(lines.as_bytes())?,
+        None if !lines.is_empty() => {
+            write!(out, "{lines}")?;
+            if !cred.message.is_empty() {
+                writeln!(out)?;
+            }
+        }
+        None => {}
+    }
+    out.write_all(&cred.message)?;
+    out.flush()?;
+
+    Ok(status.map_or(ExitCode::SUCCESS, |e| ExitCode::from(e.code() as u8)))
+}
+
+fn bench(args: BenchArgs) -> Result<ExitCode> {
+    let ctx = args.ctx.context()?;
+    let payload = match args.string {
+        Some(string) => string.into_bytes(),
+        None => (0..args.length).map(|i| i as u8).collect(),
+    };
+    let deadline = args.duration.map(Duration::from_secs);
+    let threads = args.num_threads.max(1);
+
+    if !args.quiet {
+        match deadline {
+            Some(deadline) => println!(
+                "munge-rs: Processing credentials for {}s using {threads} thread(s)",
+                deadline.as_secs()
+            ),
+            None => println!(
+                "munge-rs: Processing {} credential(s) using {threads} thread(s)",
+                args.num_creds
+            ),
+        }
+    }
+
+    let started = Instant::now();
+    let claimed = AtomicU64::new(0);
+    let done = AtomicU64::new(0);
+    let failed = AtomicU64::new(0);
+
+    thread::scope(|s| {
+        for _ in 0..threads {
+            // Each thread gets its own context, libmunge stores the last error in it.
+            let ctx = ctx.clone();
+            let (payload, claimed, done, failed) = (&payload, &claimed, &done, &failed);
+            s.spawn(move || loop {
+                match deadline {
+                    Some(deadline) if started.elapsed() >= deadline => break,
+                    None if claimed.fetch_add(1, Ordering::Relaxed) >= args.num_creds => break,
+                    _ => {}
+                }
+
+                let res = encode_bytes(payload, Some(&ctx)).and_then(|cred| match args.decode {
+                    true => decode_full_bytes(&cred, Some(&ctx)).map(|_| ()),
+                    false => Ok(()),
+                });
+                match res {
+                    Ok(()) => done.fetch_add(1, Ordering::Relaxed),
+                    Err(e) => {
+                        eprintln!("munge-rs: Error: {e}");
+                        failed.fetch_add(1, Ordering::Relaxed)
+                    }
+                };
+            });
+        }
+    });
+
+    let elapsed = started.elapsed().as_secs_f64();
+    let done = done.into_inner();
+    let failed = failed.into_inner();
+    if !args.quiet {
+        println!(
+            "munge-rs: Processed {done} credential(s) in {elapsed:.3}s ({:.0} creds/sec)",
+            done as f64 / elapsed.max(f64::EPSILON)
+        );
+    }
+    if failed > 0 {
+        eprintln!("munge-rs: {failed} credential(s) failed");
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+impl CtxArgs {
+    fn context(&self) -> Result<Context, Error> {
+        let mut builder = Context::builder();
+        if let Some(cipher) = self.cipher {
+            builder = builder.cipher(cipher);
+        }
+        if let Some(mac) = self.mac {
+            builder = builder.mac(mac);
+        }
+        if let Some(zip) = self.zip {
+            builder = builder.zip(zip);
+        }
+        if let Some(ttl) = self.ttl {
+            builder = builder.ttl(ttl);
+        }
+        if let Some(uid) = self.restrict_uid {
+            builder = builder.uid_restriction(uid);
+        }
+        if let Some(gid) = self.restrict_gid {
+            builder = builder.gid_restriction(gid);
+        }
+        if let Some(socket) = &self.socket {
+            builder = builder.socket(socket);
+        }
+        builder.build()
+    }
+}
+
+/// Builds the `unmunge` style metadata of a decoded credential.
+fn metadata(
+    status: Option<MungeError>,
+    cred: &DecodedCredential<Vec<u8>>,
+    (uid_restriction, gid_restriction): (u32, u32),
+    numeric: bool,
+) -> Vec<(&'static str, String)> {
+    let named = |name: Option<String>, value: String| match (numeric, name) {
+        (false, Some(name)) => format!("{name} ({value})"),
+        _ => value,
+    };
+
+    let mut metadata = vec![
+        (
+            "STATUS",
+            match status {
+                None => named(Some("Success".to_string()), "0".to_string()),
+                Some(e) => named(Some(e.to_string()), e.code().to_string()),
+            },
+        ),
+        (
+            "ENCODE_HOST",
+            named(
+                Some(host_name(cred.addr4).unwrap_or_else(|| "???".to_string())),
+                cred.addr4.to_string(),
+            ),
+        ),
+        ("ENCODE_TIME", time(cred.encode_time, numeric)),
+        ("DECODE_TIME", time(cred.decode_time, numeric)),
+        ("TTL", cred.ttl.to_string()),
+        (
+            "CIPHER",
+            named(
+                Some(cred.cipher.to_string()),
+                (cred.cipher as u32).to_string(),
+            ),
+        ),
+        (
+            "MAC",
+            named(Some(cred.mac.to_string()), (cred.mac as u32).to_string()),
+        ),
+        (
+            "ZIP",
+            named(Some(cred.zip.to_string()), (cred.zip as u32).to_string()),
+        ),
+        ("UID", named(user_name(cred.uid), cred.uid.to_string())),
+        ("GID", named(group_name(cred.gid), cred.gid.to_string())),
+    ];
+    if uid_restriction != u32::MAX {
+        metadata.push((
+            "UID_RESTRICTION",
+            named(user_name(uid_restriction), uid_restriction.to_string()),
+        ));
+    }
+    if gid_restriction != u32::MAX {
+        metadata.push((
+            "GID_RESTRICTION",
+            named(group_name(gid_restriction), gid_restriction.to_string()),
+        ));
+    }
+    metadata.push(("LENGTH", cred.message.len().to_string()));
+    metadata
+}
+
+fn time(time: DateTime<Utc>, numeric: bool) -> String {
+    match numeric {
+        true => time.timestamp().to_string(),
+        false => format!(
+            "{} ({})",
+            time.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S %z"),
+            time.timestamp()
+        ),
+    }
+}
+
+fn parse_cipher(s: &str) -> Result<MungeCipher, String> {
+    let cipher: MungeCipher = s.parse().map_err(|e: Error| e.to_string())?;
+    check_supported(cipher, cipher.is_supported(), MungeCipher::supported())
+}
+
+fn parse_mac(s: &str) -> Result<MungeMac, String> {
+    let mac: MungeMac = s.parse().map_err(|e: Error| e.to_string())?;
+    check_supported(mac, mac.is_supported(), MungeMac::supported())
+}
+
+fn parse_zip(s: &str) -> Result<MungeZip, String> {
+    let zip: MungeZip = s.parse().map_err(|e: Error| e.to_string())?;
+    check_supported(zip, zip.is_supported(), MungeZip::supported())
+}
+
+fn check_supported<T: Display>(
+    value: T,
+    is_supported: bool,
+    supported: impl Iterator<Item = T>,
+) -> Result<T, String> {
+    if is_supported {
+        return Ok(value);
+    }
+    let names: Vec<_> = supported.map(|value| value.to_string()).collect();
+    Err(format!(
+        "{value} is not supported by libmunge, expected one of {}",
+        names.join(", ")
+    ))
+}
+
+fn list_types<T: Display>(kind: &str, supported: impl Iterator<Item = (T, u32)>) {
+    println!("{kind} types:\n");
+    supported.for_each(|(value, num)| println!("  {value} ({num})"));
+    println!();
+}
+
+/// Parses a TTL in seconds, mapping -1 to `MUNGE_TTL_MAXIMUM`, which libmunge takes as
+/// the unsigned representation of -1.
+fn parse_ttl(s: &str) -> Result<u32, String> {
+    let ttl: i32 = s.parse().map_err(|e: ParseIntError| e.to_string())?;
+    match ttl {
+        -1 => Ok(u32::MAX),
+        ttl => u32::try_from(ttl)
+            .map_err(|_| "expected 0 for the default, -1 for the maximum or seconds".to_string()),
+    }
+}
+
+fn parse_key(s: &str) -> Result<String, String> {
+    let key = s.trim().to_ascii_uppercase();
+    match KEYS.contains(&key.as_str()) {
+        true => Ok(key),
+        false => Err(format!("expected one of {}", KEYS.join(", "))),
+    }
+}
+
+fn parse_uid(s: &str) -> Result<u32, String> {
+    if let Ok(uid) = s.parse() {
+        return Ok(uid);
+    }
+    let name = CString::new(s).map_err(|e| e.to_string())?;
+    // SAFETY: The CLI is single-threaded while parsing arguments.
+    let pw = unsafe { libc::getpwnam(name.as_ptr()) };
+    match pw.is_null() {
+        true => Err(format!("unknown user \"{s}\"")),
+        false => Ok(unsafe { (*pw).pw_uid }),
+    }
+}
+
+fn parse_gid(s: &str) -> Result<u32, String> {
+    if let Ok(gid) = s.parse() {
+        return Ok(gid);
+    }
+    let name = CString::new(s).map_err(|e| e.to_string())?;
+    // SAFETY: The CLI is single-threaded while parsing arguments.
+    let gr = unsafe { libc::getgrnam(name.as_ptr()) };
+    match gr.is_null() {
+        true => Err(format!("unknown group \"{s}\"")),
+        false => Ok(unsafe { (*gr).gr_gid }),
+    }
+}
+
+fn user_name(uid: u32) -> Option<String> {
+    // SAFETY: `decode` runs on the main thread only.
+    let pw = unsafe { libc::getpwuid(uid) };
+    (!pw.is_null()).then(|| unsafe { lossy((*pw).pw_name) })
+}
+
+fn group_name(gid: u32) -> Option<String> {
+    // SAFETY: `decode` runs on the main thread only.
+    let gr = unsafe { libc::getgrgid(gid) };
+    (!gr.is_null()).then(|| unsafe { lossy((*gr).gr_name) })
+}
+
+unsafe fn lossy(s: *const libc::c_char) -> String {
+    CStr::from_ptr(s).to_string_lossy().into_owned()
+}
+
+fn host_name(addr: Ipv4Addr) -> Option<String> {
+    let sin = libc::sockaddr_in {
+        sin_family: libc::AF_INET as libc::sa_family_t,
+        sin_port: 0,
+        sin_addr: libc::in_addr {
+            s_addr: u32::from(addr).to_be(),
+        },
+        sin_zero: [0; 8],
+    };
+    let mut host = [0 as libc::c_char; libc::NI_MAXHOST as usize];
+
+    let err = unsafe {
+        libc::getnameinfo(
+            &sin as *const libc::sockaddr_in as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+            host.as_mut_ptr(),
+            host.len() as libc::socklen_t,
+            std::ptr::null_mut(),
+            0,
+            libc::NI_NAMEREQD,
+        )
+    };
+    (err == 0).then(|| unsafe { lossy(host.as_ptr()) })
+}
+
+fn read_input(path: Option<&Path>) -> Result<Vec<u8>> {
+    let mut input = Vec::new();
+    match path {
+        None => io::stdin().read_to_end(&mut input),
+        Some(path) if path == Path::new("-") => io::stdin().read_to_end(&mut input),
+        Some(path) => File::open(path).and_then(|mut f| f.read_to_end(&mut input)),
+    }?;
+    Ok(input)
+}
+
+fn open_output(path: Option<&Path>) -> Result<Box<dyn Write>> {
+    match path {
+        None => Ok(Box::new(io::stdout())),
+        Some(path) if path == Path::new("-") => Ok(Box::new(io::stdout())),
+        Some(path) => Ok(Box::new(File::create(path)?)),
+    }
+}
+
+/// Exits with the MUNGE error code like the C tools do.
+fn exit_code(e: &(dyn std::error::Error + 'static)) -> ExitCode {
+    let err = match e.downcast_ref::<Error>() {
+        Some(e) => e.munge_error().copied(),
+        None => e.downcast_ref::<MungeError>().copied(),
+    };
+    err.map_or(ExitCode::FAILURE, |e| ExitCode::from(e.code() as u8))
+}
+
+#[cfg(test)]
+mod cli_tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn verify_cli() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn parse_types() {
+        assert_eq!(parse_cipher("AES256"), Ok(MungeCipher::Aes256));
+        assert_eq!(parse_mac("5"), Ok(MungeMac::SHA256));
+        assert_eq!(parse_zip("zlib"), Ok(MungeZip::Zlib));
+        assert!(parse_zip("lzma").is_err());
+        assert_eq!(parse_uid("0"), Ok(0));
+        assert_eq!(parse_key("uid"), Ok("UID".to_string()));
+        assert_eq!(parse_ttl("60"), Ok(60));
+        assert_eq!(parse_ttl("-1"), Ok(u32::MAX));
+        assert!(parse_ttl("-2").is_err());
+        assert!(parse_ttl("4294967295").is_err());
+    }
+}
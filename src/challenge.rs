@@ -0,0 +1,291 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    backend::{MungeBackend, Munged},
+    credential::Credential,
+    ctx::Context,
+    enums::Error,
+    handshake::random_nonce,
+};
+
+/// Time a response may take unless configured otherwise, see [`Challenge::with_max_age`].
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(60);
+
+/// Nonce issued by a verifier, binding the credential of a prover to a single request.
+///
+/// A plain credential can be captured and replayed to any service sharing the munged
+/// key within its TTL. A response to a challenge carries the audience, the nonce and
+/// the time it was created in its payload, one per line, so it is only accepted once,
+/// by the verifier that issued the nonce, and only within the maximum age. The audience
+/// comes first, so a [`crate::Policy`] with the same audience accepts responses too.
+///
+/// # Example
+///
+/// ```ignore
+/// // Verifier
+/// let challenge = Challenge::new("scheduler")?;
+/// send(challenge.nonce());
+///
+/// // Prover
+/// let response = respond_to_challenge(&nonce, "scheduler", None)?;
+///
+/// // Verifier
+/// let cred = challenge.verify(&response, None)?;
+/// println!("Authenticated UID {}", cred.uid);
+/// ```
+///
+/// A challenge cannot be cloned, so once [`Challenge::verify`] consumed it, no other
+/// response to the same nonce is accepted.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Challenge {
+    audience: String,
+    nonce: String,
+    issued_at: DateTime<Utc>,
+    max_age: Duration,
+}
+
+impl Challenge {
+    /// Issues a challenge with a fresh random nonce for the given audience, typically
+    /// the name of the verifying service.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if no random nonce can be generated.
+    pub fn new(audience: impl Into<String>) -> Result<Self, Error> {
+        let nonce = random_nonce()?.iter().map(|b| format!("{b:02x}")).collect();
+        Ok(Challenge {
+            audience: audience.into(),
+            nonce,
+            issued_at: Utc::now(),
+            max_age: DEFAULT_MAX_AGE,
+        })
+    }
+
+    /// Sets how long after issuing the challenge, and how far apart the clocks of
+    /// prover and verifier, a response is accepted. 60 seconds by default.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Returns the hex encoded nonce to send to the prover.
+    pub fn nonce(&self) -> &str {
+        &self.nonce
+    }
+
+    /// Returns the audience responses must name.
+    pub fn audience(&self) -> &str {
+        &self.audience
+    }
+
+    /// Returns the time the challenge was issued.
+    pub fn issued_at(&self) -> DateTime<Utc> {
+        self.issued_at
+    }
+
+    /// Decodes the response of the prover and checks that it answers this challenge.
+    ///
+    /// The challenge is consumed, so a nonce cannot be answered twice:
+    ///
+    /// ```compile_fail
+    /// # fn verify_twice(response: &str) -> Result<(), munge_rs::Error> {
+    /// let challenge = munge_rs::Challenge::new("scheduler")?;
+    /// challenge.verify(response, None)?;
+    /// challenge.verify(response, None)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `response` - The credential returned by [`respond_to_challenge`].
+    /// * `ctx` - An optional reference to a [`Context`]. If no context is provided, defaults will be used.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`crate::decode_bytes`], or [`Error::Challenge`] if the
+    /// response is for another nonce or audience, or is too old.
+    ///
+    /// # Returns
+    ///
+    /// The UID and GID of the prover.
+    pub fn verify(self, response: &str, ctx: Option<&Context>) -> Result<Credential<()>, Error> {
+        self.verify_with(&Munged, response, ctx)
+    }
+
+    /// Decodes the response of the prover with the given backend and checks that it
+    /// answers this challenge.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of the backend, or [`Error::Challenge`] if the response is for
+    /// another nonce or audience, or is too old.
+    pub fn verify_with<B: MungeBackend + ?Sized>(
+        self,
+        backend: &B,
+        response: &str,
+        ctx: Option<&Context>,
+    ) -> Result<Credential<()>, Error> {
+        let cred = backend.decode_bytes(response, ctx)?;
+        self.check(&cred.message, Utc::now())?;
+
+        Ok(Credential {
+            uid: cred.uid,
+            gid: cred.gid,
+            message: (),
+        })
+    }
+
+    fn check(&self, payload: &[u8], now: DateTime<Utc>) -> Result<(), Error> {
+        let payload = std::str::from_utf8(payload)
+            .map_err(|_| Error::Challenge("Response is not valid UTF-8."))?;
+        let mut lines = payload.split('\n');
+        let (Some(audience), Some(nonce), Some(timestamp), None) =
+            (lines.next(), lines.next(), lines.next(), lines.next())
+        else {
+            return Err(Error::Challenge("Response is malformed."));
+        };
+
+        if audience != self.audience {
+            return Err(Error::Challenge("Response is meant for another audience."));
+        }
+        if nonce != self.nonce {
+            return Err(Error::Challenge("Response answers another challenge."));
+        }
+
+        let timestamp: i64 = timestamp
+            .parse()
+            .map_err(|_| Error::Challenge("Response is malformed."))?;
+        let max_age = self.max_age.as_secs() as i64;
+        if now.timestamp() - self.issued_at.timestamp() > max_age
+            || (now.timestamp() - timestamp).abs() > max_age
+        {
+            return Err(Error::Challenge("Response is too old."));
+        }
+        Ok(())
+    }
+}
+
+/// Encodes the response of a prover to a [`Challenge`].
+///
+/// # Arguments
+///
+/// * `nonce` - The nonce of the challenge, as returned by [`Challenge::nonce`].
+/// * `audience` - The audience of the challenge, typically the name of the verifier.
+/// * `ctx` - An optional reference to a [`Context`]. If no context is provided, defaults will be used.
+///
+/// # Errors
+///
+/// Returns [`Error::Challenge`] if the nonce or audience contain a newline, or the
+/// errors of [`crate::encode_bytes`].
+///
+/// # Returns
+///
+/// The credential to send back to the verifier.
+pub fn respond_to_challenge(
+    nonce: &str,
+    audience: &str,
+    ctx: Option<&Context>,
+) -> Result<String, Error> {
+    respond_to_challenge_with(&Munged, nonce, audience, ctx)
+}
+
+/// Encodes the response of a prover to a [`Challenge`] with the given backend.
+///
+/// # Errors
+///
+/// Returns [`Error::Challenge`] if the nonce or audience contain a newline, or the
+/// errors of the backend.
+pub fn respond_to_challenge_with<B: MungeBackend + ?Sized>(
+    backend: &B,
+    nonce: &str,
+    audience: &str,
+    ctx: Option<&Context>,
+) -> Result<String, Error> {
+    if nonce.contains('\n') || audience.contains('\n') {
+        return Err(Error::Challenge(
+            "Nonce and audience must not contain newlines.",
+        ));
+    }
+    let payload = format!("{audience}\n{nonce}\n{}", Utc::now().timestamp());
+    backend.encode_bytes(payload.as_bytes(), ctx)
+}
+
+#[cfg(test)]
+mod challenge_tests {
+    use std::time::Duration;
+
+    use crate::{
+        challenge::{respond_to_challenge, respond_to_challenge_with, Challenge},
+        enums::{Error, MungeError},
+        mock::MockBackend,
+    };
+
+    #[test]
+    fn check() {
+        let challenge = Challenge::new("scheduler")
+            .unwrap()
+            .with_max_age(Duration::from_secs(30));
+        let now = challenge.issued_at();
+        let payload = |aud: &str, nonce: &str, ts: i64| format!("{aud}\n{nonce}\n{ts}");
+        let nonce = challenge.nonce().to_string();
+        let ts = now.timestamp();
+
+        assert!(challenge
+            .check(payload("scheduler", &nonce, ts).as_bytes(), now)
+            .is_ok());
+
+        let rejected = |payload: String, now| {
+            matches!(
+                challenge.check(payload.as_bytes(), now),
+                Err(Error::Challenge(_))
+            )
+        };
+        assert!(rejected(payload("worker", &nonce, ts), now));
+        assert!(rejected(payload("scheduler", "00", ts), now));
+        assert!(rejected(payload("scheduler", &nonce, ts - 31), now));
+        assert!(rejected(
+            payload("scheduler", &nonce, ts + 31),
+            now + chrono::Duration::seconds(31)
+        ));
+        assert!(rejected(format!("scheduler\n{nonce}"), now));
+    }
+
+    #[test]
+    fn fresh_nonces() {
+        let a = Challenge::new("scheduler").unwrap();
+        let b = Challenge::new("scheduler").unwrap();
+
+        assert_eq!(a.nonce().len(), 64);
+        assert_ne!(a.nonce(), b.nonce());
+        assert!(respond_to_challenge("a\nb", "scheduler", None).is_err());
+    }
+
+    #[test]
+    fn verify() {
+        let mock = MockBackend::new().with_uid(1000);
+        let challenge = Challenge::new("scheduler").unwrap();
+        let response = respond_to_challenge_with(&mock, challenge.nonce(), "scheduler", None)
+            .expect("Failed to respond");
+
+        let cred = challenge
+            .verify_with(&mock, &response, None)
+            .expect("Failed to verify");
+        assert_eq!(cred.uid, 1000);
+
+        // Rejected as replayed.
+        let other = Challenge::new("scheduler").unwrap();
+        let err = other.verify_with(&mock, &response, None).unwrap_err();
+        assert_eq!(err.munge_error(), Some(&MungeError::CredReplayed));
+
+        // Rejected as answering another nonce.
+        let other = Challenge::new("scheduler").unwrap();
+        let response = respond_to_challenge_with(&mock, "00", "scheduler", None).unwrap();
+        assert!(matches!(
+            other.verify_with(&mock, &response, None),
+            Err(Error::Challenge(_))
+        ));
+    }
+}
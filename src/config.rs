@@ -0,0 +1,315 @@
+use std::{env, fmt::Display, path::PathBuf, str::FromStr};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ctx::{Context, ContextBuilder},
+    enums::Error,
+    MungeCipher, MungeMac, MungeZip,
+};
+
+/// Context settings loaded from the environment or a configuration file.
+///
+/// Settings that are not present keep the MUNGE defaults. [`Context::config`] takes a
+/// snapshot of all settings of a context.
+///
+/// With the `serde` feature the struct can be (de)serialized, eg. as part of an
+/// application's own configuration, with the cipher, MAC and compression types given by
+/// name (eg. `cipher = "aes256"`).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(deny_unknown_fields)
+)]
+pub struct ContextConfig {
+    /// Path of the munged socket.
+    pub socket: Option<PathBuf>,
+    /// Time-to-live of encoded credentials in seconds.
+    pub ttl: Option<u32>,
+    /// Cipher type of encoded credentials.
+    pub cipher: Option<MungeCipher>,
+    /// MAC type of encoded credentials.
+    pub mac: Option<MungeMac>,
+    /// Compression type of encoded credentials.
+    pub zip: Option<MungeZip>,
+    /// UID allowed to decode encoded credentials.
+    pub uid_restriction: Option<libc::uid_t>,
+    /// GID allowed to decode encoded credentials.
+    pub gid_restriction: Option<libc::gid_t>,
+}
+
+impl ContextConfig {
+    /// Reads the settings from the environment.
+    ///
+    /// The variables `MUNGE_SOCKET`, `MUNGE_TTL`, `MUNGE_CIPHER`, `MUNGE_MAC`, `MUNGE_ZIP`,
+    /// `MUNGE_UID_RESTRICTION` and `MUNGE_GID_RESTRICTION` are used. Unset or empty
+    /// variables are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidConfig`] if a variable cannot be parsed.
+    pub fn from_env() -> Result<Self, Error> {
+        Self::from_lookup(|name| env::var(name).ok())
+    }
+
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, Error> {
+        let var = |name: &str| lookup(name).filter(|value| !value.is_empty());
+
+        Ok(ContextConfig {
+            socket: var("MUNGE_SOCKET").map(PathBuf::from),
+            ttl: parse_var("MUNGE_TTL", var("MUNGE_TTL"))?,
+            cipher: parse_var("MUNGE_CIPHER", var("MUNGE_CIPHER"))?,
+            mac: parse_var("MUNGE_MAC", var("MUNGE_MAC"))?,
+            zip: parse_var("MUNGE_ZIP", var("MUNGE_ZIP"))?,
+            uid_restriction: parse_var("MUNGE_UID_RESTRICTION", var("MUNGE_UID_RESTRICTION"))?,
+            gid_restriction: parse_var("MUNGE_GID_RESTRICTION", var("MUNGE_GID_RESTRICTION"))?,
+        })
+    }
+
+    /// Reads the settings from the `[munge]` table of a TOML file.
+    ///
+    /// Other tables of the file are ignored, so the settings can live in the
+    /// application's configuration file. A file without a `[munge]` table yields the
+    /// default settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidConfig`] if the file cannot be read or the table is invalid.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// // [munge]
+    /// // socket = "/run/munge/munge.socket.2"
+    /// // ttl = 120
+    /// // cipher = "aes256"
+    /// let config = ContextConfig::from_file("/etc/myapp/config.toml")?;
+    /// ```
+    #[cfg(feature = "config")]
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> Result<Self, Error> {
+        #[derive(Deserialize)]
+        struct File {
+            #[serde(default)]
+            munge: ContextConfig,
+        }
+
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| Error::InvalidConfig(format!("{}: {e}", path.display())))?;
+        let file: File = toml::from_str(&text)
+            .map_err(|e| Error::InvalidConfig(format!("{}: {e}", path.display())))?;
+        Ok(file.munge)
+    }
+
+    /// Returns a [`ContextBuilder`] with the settings that are present.
+    pub fn builder(&self) -> ContextBuilder {
+        let mut builder = Context::builder();
+        if let Some(socket) = &self.socket {
+            builder = builder.socket(socket);
+        }
+        if let Some(ttl) = self.ttl {
+            builder = builder.ttl(ttl);
+        }
+        if let Some(cipher) = self.cipher {
+            builder = builder.cipher(cipher);
+        }
+        if let Some(mac) = self.mac {
+            builder = builder.mac(mac);
+        }
+        if let Some(zip) = self.zip {
+            builder = builder.zip(zip);
+        }
+        if let Some(uid) = self.uid_restriction {
+            builder = builder.uid_restriction(uid);
+        }
+        if let Some(gid) = self.gid_restriction {
+            builder = builder.gid_restriction(gid);
+        }
+        builder
+    }
+
+    /// Creates a [`Context`] with the settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`ContextBuilder::build`].
+    pub fn build(&self) -> Result<Context, Error> {
+        self.builder().build()
+    }
+}
+
+impl Context {
+    /// Creates a context configured from the `MUNGE_*` environment variables.
+    ///
+    /// See [`ContextConfig::from_env`] for the variables used.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidConfig`] if a variable cannot be parsed, or the errors of
+    /// [`ContextBuilder::build`].
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// // MUNGE_SOCKET=/run/munge/munge.socket.2 MUNGE_CIPHER=aes256 ./app
+    /// let ctx = Context::from_env()?;
+    /// ```
+    pub fn from_env() -> Result<Self, Error> {
+        ContextConfig::from_env()?.build()
+    }
+
+    /// Creates a context configured from the `[munge]` table of a TOML file.
+    ///
+    /// See [`ContextConfig::from_file`] for the file format.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidConfig`] if the file cannot be read or is invalid, or the
+    /// errors of [`ContextBuilder::build`].
+    #[cfg(feature = "config")]
+    pub fn from_config_file(path: impl AsRef<std::path::Path>) -> Result<Self, Error> {
+        ContextConfig::from_file(path)?.build()
+    }
+
+    /// Creates a context with the given settings.
+    ///
+    /// This is the inverse of [`Context::config`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`ContextBuilder::build`].
+    pub fn from_config(config: &ContextConfig) -> Result<Self, Error> {
+        config.build()
+    }
+
+    /// Takes a snapshot of the settings of the context.
+    ///
+    /// All settings are present in the snapshot, including those still at their
+    /// defaults, so the snapshot can be persisted and restored with
+    /// [`Context::from_config`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`Error`] if one of the settings cannot be read.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let profile = serde_json::to_string(&ctx.config()?)?;
+    /// let ctx = Context::from_config(&serde_json::from_str(&profile)?)?;
+    /// ```
+    pub fn config(&self) -> Result<ContextConfig, Error> {
+        Ok(ContextConfig {
+            socket: Some(self.socket()?),
+            // A TTL of MUNGE_TTL_MAXIMUM (-1) is kept as its unsigned representation.
+            ttl: Some(self.ttl()? as u32),
+            cipher: Some(self.cipher()?),
+            mac: Some(self.mac()?),
+            zip: Some(self.zip()?),
+            uid_restriction: Some(self.uid_restriction()?),
+            gid_restriction: Some(self.gid_restriction()?),
+        })
+    }
+}
+
+fn parse_var<T>(name: &str, value: Option<String>) -> Result<Option<T>, Error>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .map(|value| value.trim().parse())
+        .transpose()
+        .map_err(|e| Error::InvalidConfig(format!("{name}: {e}")))
+}
+
+#[cfg(test)]
+mod config_tests {
+    use std::{collections::HashMap, path::PathBuf};
+
+    use crate::{config::ContextConfig, ctx::Context, enums::Error, MungeCipher};
+
+    #[test]
+    fn from_lookup() {
+        let vars = HashMap::from([
+            ("MUNGE_SOCKET", "/tmp/munge.socket"),
+            ("MUNGE_TTL", "120"),
+            ("MUNGE_CIPHER", "AES256"),
+            ("MUNGE_ZIP", ""),
+        ]);
+        let config =
+            ContextConfig::from_lookup(|name| vars.get(name).map(|v| v.to_string())).unwrap();
+
+        assert_eq!(config.socket, Some(PathBuf::from("/tmp/munge.socket")));
+        assert_eq!(config.ttl, Some(120));
+        assert_eq!(config.cipher, Some(MungeCipher::Aes256));
+        assert_eq!(config.mac, None);
+        assert_eq!(config.zip, None);
+
+        let ctx = config.build().unwrap();
+        assert_eq!(ctx.ttl().unwrap(), 120);
+    }
+
+    #[test]
+    fn from_lookup_invalid() {
+        let res =
+            ContextConfig::from_lookup(|name| (name == "MUNGE_MAC").then(|| "sha3".to_string()));
+        assert!(matches!(res, Err(Error::InvalidConfig(_))));
+    }
+
+    #[cfg(feature = "config")]
+    #[test]
+    fn from_file() {
+        let path = std::env::temp_dir().join(format!("munge-rs-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[server]\nport = 8080\n\n[munge]\nttl = 60\ncipher = \"aes128\"\nzip = \"zlib\"\n",
+        )
+        .unwrap();
+        let config = ContextConfig::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let config = config.unwrap();
+        assert_eq!(config.ttl, Some(60));
+        assert_eq!(config.cipher, Some(MungeCipher::Aes128));
+        assert_eq!(config.zip, Some(crate::MungeZip::Zlib));
+        assert_eq!(config.socket, None);
+    }
+
+    #[test]
+    fn config_round_trip() {
+        let ctx = Context::builder()
+            .ttl(90)
+            .cipher(MungeCipher::Aes256)
+            .uid_restriction(1000)
+            .build()
+            .unwrap();
+
+        let config = ctx.config().unwrap();
+        assert_eq!(config.ttl, Some(90));
+        assert_eq!(config.cipher, Some(MungeCipher::Aes256));
+        assert!(config.socket.is_some());
+
+        let restored = Context::from_config(&config).unwrap();
+        assert_eq!(restored.config().unwrap(), config);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn config_serde() {
+        let config = ContextConfig {
+            ttl: Some(60),
+            mac: Some(crate::MungeMac::SHA512),
+            ..Default::default()
+        };
+
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(json.contains(r#""mac":"sha512""#));
+        assert_eq!(
+            serde_json::from_str::<ContextConfig>(&json).unwrap(),
+            config
+        );
+    }
+}
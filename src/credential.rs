@@ -1,13 +1,116 @@
+use std::{net::Ipv4Addr, string::FromUtf8Error};
+
+use chrono::{DateTime, Utc};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{MungeCipher, MungeMac, MungeZip};
+
 /// Credential containing user and group information.
 ///
 /// The `Credential` struct encapsulates the user ID, group ID, and the associated
 /// message retrieved during the encoding or decoding process.
+///
+/// The message is a UTF-8 [`String`] by default. Credentials decoded with
+/// [`crate::decode_bytes`] carry the raw payload as a `Vec<u8>` instead.
+///
+/// With the `serde` feature, credentials can be (de)serialized for any serializable
+/// message type.
 #[derive(Debug, Default, Clone)]
-pub struct Credential {
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Credential<T = String> {
+    /// User ID (UID) associated with the credential.
+    pub uid: u32,
+    /// Group ID (GID) associated with the credential.
+    pub gid: u32,
+    /// Message contained within the credential.
+    pub message: T,
+}
+
+/// Credential with the raw binary payload, as returned by [`crate::decode_bytes`].
+///
+/// The tower and tonic layers insert the decoded credential into the request extensions
+/// as a `RawCredential`, and also as a UTF-8 [`Credential`] if its payload is valid
+/// UTF-8.
+pub type RawCredential = Credential<Vec<u8>>;
+
+impl TryFrom<Credential<Vec<u8>>> for Credential {
+    type Error = FromUtf8Error;
+
+    /// Converts a binary credential into one with a UTF-8 message.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`FromUtf8Error`] if the payload is not valid UTF-8.
+    fn try_from(cred: Credential<Vec<u8>>) -> Result<Self, Self::Error> {
+        Ok(Credential {
+            uid: cred.uid,
+            gid: cred.gid,
+            message: String::from_utf8(cred.message)?,
+        })
+    }
+}
+
+/// Credential together with the metadata reported by MUNGE after decoding.
+///
+/// This mirrors the information printed by `unmunge`: besides the user ID, group ID
+/// and message, it records where and when the credential was encoded and which
+/// cipher, MAC and compression types were used.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DecodedCredential<T = String> {
     /// User ID (UID) associated with the credential.
     pub uid: u32,
     /// Group ID (GID) associated with the credential.
     pub gid: u32,
-    /// Message string contained within the credential.
-    pub message: String,
+    /// Message contained within the credential.
+    pub message: T,
+    /// Time at which the credential was encoded.
+    pub encode_time: DateTime<Utc>,
+    /// Time at which the credential was decoded.
+    pub decode_time: DateTime<Utc>,
+    /// IPv4 address of the host that encoded the credential.
+    pub addr4: Ipv4Addr,
+    /// Cipher type used to encrypt the credential.
+    pub cipher: MungeCipher,
+    /// MAC type used to sign the credential.
+    pub mac: MungeMac,
+    /// Compression type used for the payload.
+    pub zip: MungeZip,
+    /// Time-to-live of the credential in seconds.
+    pub ttl: i32,
+}
+
+impl<T> From<DecodedCredential<T>> for Credential<T> {
+    fn from(cred: DecodedCredential<T>) -> Self {
+        Credential {
+            uid: cred.uid,
+            gid: cred.gid,
+            message: cred.message,
+        }
+    }
+}
+
+impl TryFrom<DecodedCredential<Vec<u8>>> for DecodedCredential {
+    type Error = FromUtf8Error;
+
+    /// Converts a binary decoded credential into one with a UTF-8 message.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`FromUtf8Error`] if the payload is not valid UTF-8.
+    fn try_from(cred: DecodedCredential<Vec<u8>>) -> Result<Self, Self::Error> {
+        Ok(DecodedCredential {
+            uid: cred.uid,
+            gid: cred.gid,
+            message: String::from_utf8(cred.message)?,
+            encode_time: cred.encode_time,
+            decode_time: cred.decode_time,
+            addr4: cred.addr4,
+            cipher: cred.cipher,
+            mac: cred.mac,
+            zip: cred.zip,
+            ttl: cred.ttl,
+        })
+    }
 }
@@ -1,29 +1,23 @@
 #![allow(clippy::new_without_default)]
 
 use core::fmt;
-use std::{
-    ffi::{self, CStr, CString},
-    net::Ipv4Addr,
-    path::PathBuf,
-    ptr,
-    str::Utf8Error,
-};
+use std::{ffi::CString, net::Ipv4Addr, path::PathBuf, str::Utf8Error};
 
 use chrono::{DateTime, Utc};
 
 use crate::{
     enums::{Error, MungeError, MungeOption},
-    MungeCipher, MungeMac, MungeZip,
+    retry::RetryPolicy,
+    sys, MungeCipher, MungeMac, MungeZip,
 };
 
 /// Context used for managing options and settings.
+#[derive(Clone)]
 pub struct Context {
-    pub(crate) ctx: *mut crate::ffi::munge_ctx,
+    pub(crate) ctx: sys::RawCtx,
+    retry: Option<RetryPolicy>,
 }
 
-unsafe impl Send for Context {}
-unsafe impl Sync for Context {}
-
 impl fmt::Debug for Context {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Context")
@@ -32,18 +26,51 @@ impl fmt::Debug for Context {
             .field("mac", &self.mac())
             .field("zip", &self.zip())
             .field("cipher", &self.cipher())
+            .field("retry_policy", &self.retry)
             .finish()
     }
 }
 
 impl Context {
     /// Create a new [`Context`]
+    ///
+    /// # Panics
+    ///
+    /// Panics if the context cannot be allocated, see [`Context::try_new`].
     pub fn new() -> Self {
-        Context {
-            ctx: unsafe { crate::ffi::munge_ctx_create() },
+        Context::try_new().expect("Failed to create MUNGE context")
+    }
+
+    /// Create a new [`Context`], failing instead of panicking if it cannot be allocated.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MungeError::NoMemory`] if `munge_ctx_create` fails.
+    pub fn try_new() -> Result<Self, Error> {
+        match sys::RawCtx::try_new() {
+            Some(ctx) => Ok(Context { ctx, retry: None }),
+            None => Err(Error::MungeError(
+                MungeError::NoMemory,
+                "Unable to create MUNGE context.".to_string(),
+            )),
         }
     }
 
+    /// Returns a [`ContextBuilder`] for configuring a new context in one place.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let ctx = Context::builder()
+    ///     .socket("/run/munge/munge.socket.2")
+    ///     .ttl(60)
+    ///     .cipher(MungeCipher::Aes256)
+    ///     .build()?;
+    /// ```
+    pub fn builder() -> ContextBuilder {
+        ContextBuilder::default()
+    }
+
     /// Sets the socket path in the context to the given `PathBuf`.
     ///
     /// # Arguments
@@ -74,9 +101,7 @@ impl Context {
 
         let c_path = CString::new(socket.to_str().ok_or(Error::NonUtf8SocketPath)?)?;
 
-        let _err = unsafe {
-            crate::ffi::munge_ctx_set(self.ctx, MungeOption::Socket as i32, c_path.as_ptr())
-        };
+        let _err = self.ctx.set_str(MungeOption::Socket, &c_path);
 
         if _err != 0 {
             Err(Error::MungeError(
@@ -91,6 +116,27 @@ impl Context {
         }
     }
 
+    /// Sets the policy for retrying encode and decode calls that fail to reach munged.
+    ///
+    /// Without a policy, which is the default, such calls fail right away. See
+    /// [`RetryPolicy`] for which errors are retried.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut ctx = Context::new();
+    /// ctx.set_retry_policy(Some(RetryPolicy::new(5)));
+    /// ```
+    pub fn set_retry_policy(&mut self, policy: Option<RetryPolicy>) -> &mut Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Returns the retry policy of the context, if any.
+    pub fn retry_policy(&self) -> Option<&RetryPolicy> {
+        self.retry.as_ref()
+    }
+
     /// Sets the specified context option to the given value.
     ///
     /// # Arguments
@@ -120,7 +166,7 @@ impl Context {
         option: MungeOption,
         value: u32,
     ) -> Result<&mut Self, MungeError> {
-        let _err = unsafe { crate::ffi::munge_ctx_set(self.ctx, option as i32, value) };
+        let _err = self.ctx.set_u32(option, value);
         if _err != 0 {
             Err(MungeError::from_u32(_err))
         } else {
@@ -322,9 +368,7 @@ impl Context {
     /// }
     /// ```
     pub(crate) fn get_ctx_opt(&self, option: MungeOption) -> Result<i32, Error> {
-        let mut value: i32 = 42;
-
-        let _err = unsafe { crate::ffi::munge_ctx_get(self.ctx, option as i32, &mut value) };
+        let (_err, value) = split(self.ctx.get_i32(option));
 
         self.error_check_i32(_err, value)
     }
@@ -424,7 +468,7 @@ impl Context {
     /// }
     /// ```
     pub fn cipher(&self) -> Result<MungeCipher, Error> {
-        match self.get_ctx_opt(MungeOption::ZipType) {
+        match self.get_ctx_opt(MungeOption::CipherType) {
             Ok(cipher) => Ok(MungeCipher::try_from(cipher as u32)?),
             Err(e) => Err(e),
         }
@@ -452,10 +496,7 @@ impl Context {
     /// let addr4 = ctx.addr4().unwrap();
     /// ```
     pub fn addr4(&self) -> Result<Ipv4Addr, Error> {
-        let mut value: u32 = 42;
-
-        let _err =
-            unsafe { crate::ffi::munge_ctx_get(self.ctx, MungeOption::Addr4 as i32, &mut value) };
+        let (_err, value) = split(self.ctx.get_u32(MungeOption::Addr4));
 
         if _err != 0 {
             Err(Error::MungeError(
@@ -495,11 +536,7 @@ impl Context {
     /// }
     /// ```
     pub fn encode_time(&self) -> Result<DateTime<Utc>, Error> {
-        let mut c_time: libc::time_t = 0i64;
-
-        let _err = unsafe {
-            crate::ffi::munge_ctx_get(self.ctx, MungeOption::EncodeTime as i32, &mut c_time)
-        };
+        let (_err, c_time) = split(self.ctx.get_time(MungeOption::EncodeTime));
 
         let date_time: DateTime<Utc> =
             DateTime::from_timestamp(c_time, 0).ok_or(Error::InvalidTime)?;
@@ -532,11 +569,7 @@ impl Context {
     /// }
     /// ```
     pub fn decode_time(&self) -> Result<DateTime<Utc>, Error> {
-        let mut c_time: libc::time_t = 0i64;
-
-        let _err = unsafe {
-            crate::ffi::munge_ctx_get(self.ctx, MungeOption::DecodeTime as i32, &mut c_time)
-        };
+        let (_err, c_time) = split(self.ctx.get_time(MungeOption::DecodeTime));
 
         let date_time: DateTime<Utc> =
             DateTime::from_timestamp(c_time, 0).ok_or(Error::InvalidTime)?;
@@ -567,11 +600,7 @@ impl Context {
     /// }
     /// ```
     pub fn uid_restriction(&self) -> Result<libc::uid_t, Error> {
-        let mut c_uid: libc::uid_t = 0;
-
-        let _err = unsafe {
-            crate::ffi::munge_ctx_get(self.ctx, MungeOption::UidRestriction as i32, &mut c_uid)
-        };
+        let (_err, c_uid) = split(self.ctx.get_u32(MungeOption::UidRestriction));
 
         self.error_check_u32(_err, c_uid)
     }
@@ -599,11 +628,7 @@ impl Context {
     /// }
     /// ```
     pub fn gid_restriction(&self) -> Result<libc::gid_t, Error> {
-        let mut c_gid: libc::gid_t = 0;
-
-        let _err = unsafe {
-            crate::ffi::munge_ctx_get(self.ctx, MungeOption::GidRestriction as i32, &mut c_gid)
-        };
+        let (_err, c_gid) = split(self.ctx.get_u32(MungeOption::GidRestriction));
 
         self.error_check_u32(_err, c_gid)
     }
@@ -625,11 +650,8 @@ impl Context {
     /// }
     /// ```
     pub fn socket(&self) -> Result<PathBuf, Error> {
-        let mut c_path: *const ffi::c_char = ptr::null();
-
-        let _err =
-            unsafe { crate::ffi::munge_ctx_get(self.ctx, MungeOption::Socket as i32, &mut c_path) };
-        let socket = unsafe { CStr::from_ptr(c_path) }.to_str()?.to_owned();
+        let (_err, c_path) = split(self.ctx.get_str(MungeOption::Socket));
+        let socket = c_path.to_str()?.to_owned();
 
         if _err != 0 {
             // Err(MungeError::from_u32(_err).into())
@@ -664,14 +686,12 @@ impl Context {
     /// }
     /// ```
     pub(crate) fn str_error(&self) -> Result<Option<String>, Utf8Error> {
-        let err: *const libc::c_char = unsafe { crate::ffi::munge_ctx_strerror(self.ctx) };
-
-        if err.is_null() {
+        let Some(err) = self.ctx.strerror() else {
             return Ok(None); // No error condition
-        }
+        };
 
         // The conversion from CStr to &str and from &str to String
-        let out_err = Some(unsafe { CStr::from_ptr(err) }.to_str()?);
+        let out_err = Some(err.to_str()?);
 
         // Return the error message if parsing was successful, otherwise None
         Ok(out_err.map(|s| s.to_string()))
@@ -772,17 +792,134 @@ impl Context {
     }
 }
 
-impl Drop for Context {
-    fn drop(&mut self) {
-        unsafe { crate::ffi::munge_ctx_destroy(self.ctx) };
-    }
+/// Builder for a [`Context`].
+///
+/// Unlike the `set_*` methods of [`Context`], which fail one by one with different error
+/// types, the options are only applied by [`ContextBuilder::build`], which reports the
+/// first invalid option as an [`Error`].
+#[derive(Debug, Default, Clone)]
+pub struct ContextBuilder {
+    socket: Option<PathBuf>,
+    ttl: Option<u32>,
+    cipher: Option<MungeCipher>,
+    mac: Option<MungeMac>,
+    zip: Option<MungeZip>,
+    uid_restriction: Option<libc::uid_t>,
+    gid_restriction: Option<libc::gid_t>,
+    retry_policy: Option<RetryPolicy>,
 }
 
-impl Clone for Context {
-    fn clone(&self) -> Self {
-        Self {
-            ctx: unsafe { crate::ffi::munge_ctx_copy(self.ctx) },
+impl ContextBuilder {
+    /// Sets the path of the munged socket.
+    pub fn socket(mut self, path: impl Into<PathBuf>) -> Self {
+        self.socket = Some(path.into());
+        self
+    }
+
+    /// Sets the time-to-live (TTL) of encoded credentials in seconds.
+    pub fn ttl(mut self, ttl: u32) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Sets the cipher type of encoded credentials.
+    pub fn cipher(mut self, cipher: MungeCipher) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
+
+    /// Sets the MAC type of encoded credentials.
+    pub fn mac(mut self, mac: MungeMac) -> Self {
+        self.mac = Some(mac);
+        self
+    }
+
+    /// Sets the compression type of encoded credentials.
+    pub fn zip(mut self, zip: MungeZip) -> Self {
+        self.zip = Some(zip);
+        self
+    }
+
+    /// Restricts decoding of encoded credentials to the given UID.
+    pub fn uid_restriction(mut self, uid: libc::uid_t) -> Self {
+        self.uid_restriction = Some(uid);
+        self
+    }
+
+    /// Restricts decoding of encoded credentials to the given GID.
+    pub fn gid_restriction(mut self, gid: libc::gid_t) -> Self {
+        self.gid_restriction = Some(gid);
+        self
+    }
+
+    /// Sets the policy for retrying calls that fail to reach munged.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Creates the context and applies all options that were set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`Error`] if the context cannot be created, the socket path is not
+    /// valid UTF-8 or contains a NUL byte, the cipher, MAC or compression type is not
+    /// supported by libmunge, or MUNGE rejects one of the options.
+    pub fn build(&self) -> Result<Context, Error> {
+        if let Some(cipher) = self.cipher.filter(|cipher| !cipher.is_supported()) {
+            return Err(unsupported(MungeError::BadCipher, "Cipher", cipher));
+        }
+        if let Some(mac) = self.mac.filter(|mac| !mac.is_supported()) {
+            return Err(unsupported(MungeError::BadMac, "MAC", mac));
+        }
+        if let Some(zip) = self.zip.filter(|zip| !zip.is_supported()) {
+            return Err(unsupported(MungeError::BadZip, "Compression", zip));
+        }
+
+        let mut ctx = Context::try_new()?;
+        ctx.set_retry_policy(self.retry_policy.clone());
+
+        if let Some(socket) = &self.socket {
+            ctx.set_socket(socket.clone())?;
+        }
+
+        let options = [
+            (MungeOption::Ttl, self.ttl),
+            (MungeOption::CipherType, self.cipher.map(|c| c as u32)),
+            (MungeOption::MacType, self.mac.map(|m| m as u32)),
+            (MungeOption::ZipType, self.zip.map(|z| z as u32)),
+            (MungeOption::UidRestriction, self.uid_restriction),
+            (MungeOption::GidRestriction, self.gid_restriction),
+        ];
+        for (option, value) in options {
+            let Some(value) = value else {
+                continue;
+            };
+            if let Err(e) = ctx.set_ctx_opt(option, value) {
+                return Err(Error::MungeError(
+                    e,
+                    match ctx.str_error()? {
+                        Some(s) => s,
+                        None => format!("Unable to set {option:?}."),
+                    },
+                ));
+            }
         }
+
+        Ok(ctx)
+    }
+}
+
+fn unsupported(err: MungeError, kind: &str, value: impl fmt::Display) -> Error {
+    Error::MungeError(err, format!("{kind} type {value} is not supported."))
+}
+
+/// Splits the result of a low-level getter into the MUNGE error code and the value,
+/// falling back to the default value on error.
+fn split<T: Default>(res: Result<T, u32>) -> (u32, T) {
+    match res {
+        Ok(value) => (0, value),
+        Err(err) => (err, T::default()),
     }
 }
 
@@ -790,7 +927,7 @@ impl Clone for Context {
 mod context_tests {
     use crate::{
         ctx::Context,
-        enums::{MungeCipher, MungeMac, MungeOption, MungeZip},
+        enums::{Error, MungeCipher, MungeMac, MungeOption, MungeZip},
     };
 
     #[test]
@@ -805,6 +942,32 @@ mod context_tests {
         // assert_eq!(ctx, ctx_copy);
     }
 
+    #[test]
+    fn builder_test() {
+        let ctx = Context::builder()
+            .ttl(90)
+            .cipher(MungeCipher::Aes256)
+            .mac(MungeMac::SHA512)
+            .zip(MungeZip::Zlib)
+            .uid_restriction(1000)
+            .gid_restriction(100)
+            .build()
+            .unwrap();
+
+        assert_eq!(ctx.ttl().unwrap(), 90);
+        assert_eq!(ctx.cipher().unwrap(), MungeCipher::Aes256);
+        assert_eq!(ctx.mac().unwrap(), MungeMac::SHA512);
+        assert_eq!(ctx.zip().unwrap(), MungeZip::Zlib);
+        assert_eq!(ctx.uid_restriction().unwrap(), 1000);
+        assert_eq!(ctx.gid_restriction().unwrap(), 100);
+    }
+
+    #[test]
+    fn builder_invalid_socket() {
+        let res = Context::builder().socket("/run/munge\0socket").build();
+        assert!(matches!(res, Err(Error::InnerNull(_))));
+    }
+
     #[test]
     fn str_err_test() {
         let ctx = Context::new();
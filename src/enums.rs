@@ -1,8 +1,13 @@
 use num_enum::{TryFromPrimitive, TryFromPrimitiveError};
-use std::{ffi::NulError, str::Utf8Error, string::FromUtf8Error};
+use std::{
+    ffi::{CString, NulError},
+    fmt,
+    str::{FromStr, Utf8Error},
+    string::FromUtf8Error,
+};
 use thiserror::Error;
 
-use crate::ffi as c;
+use crate::{ffi as c, sys, DecodedCredential};
 
 /// Context options.
 ///
@@ -24,11 +29,34 @@ pub(crate) enum MungeOption {
     GidRestriction = c::munge_opt_MUNGE_OPT_GID_RESTRICTION,
 }
 
+/// Kinds of types libmunge has names for.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MungeEnum {
+    Cipher = c::munge_enum_MUNGE_ENUM_CIPHER,
+    Mac = c::munge_enum_MUNGE_ENUM_MAC,
+    Zip = c::munge_enum_MUNGE_ENUM_ZIP,
+}
+
+impl MungeEnum {
+    fn label(self) -> &'static str {
+        match self {
+            MungeEnum::Cipher => "cipher",
+            MungeEnum::Mac => "MAC",
+            MungeEnum::Zip => "zip",
+        }
+    }
+}
+
 /// Possible error codes returned by the MUNGE library.
 ///
 /// These error codes are mapped to their corresponding constants in the MUNGE C library.
+///
+/// The enum is non-exhaustive, as newer MUNGE releases and this crate may add error
+/// codes, so matches outside the crate need a wildcard arm.
 #[repr(u32)]
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[non_exhaustive]
 pub enum MungeError {
     #[error("Snafu error")]
     Snafu = c::munge_err_EMUNGE_SNAFU,
@@ -83,11 +111,17 @@ pub enum MungeError {
 
     #[error("Credential unauthorized")]
     CredUnauthorized = c::munge_err_EMUNGE_CRED_UNAUTHORIZED,
+
+    /// An error code this crate does not know, eg. from a newer libmunge.
+    #[error("Unknown error code {0}")]
+    Unknown(u32),
 }
 
 impl MungeError {
     /// Converts a raw error code (`u32`) from the MUNGE library into a `MungeError`.
     ///
+    /// Codes that are not known to this crate are kept as [`MungeError::Unknown`].
+    ///
     /// # Arguments
     ///
     /// * `err` - The raw error code to convert.
@@ -103,7 +137,70 @@ impl MungeError {
     /// println!("Error: {:?}", error);
     /// ```
     pub fn from_u32(err: u32) -> MungeError {
-        match err {
+        MungeError::try_from(err).unwrap_or(MungeError::Unknown(err))
+    }
+
+    /// Returns the raw MUNGE error code.
+    pub fn code(&self) -> u32 {
+        match *self {
+            MungeError::Snafu => c::munge_err_EMUNGE_SNAFU,
+            MungeError::BadArg => c::munge_err_EMUNGE_BAD_ARG,
+            MungeError::BadLength => c::munge_err_EMUNGE_BAD_LENGTH,
+            MungeError::Overflow => c::munge_err_EMUNGE_OVERFLOW,
+            MungeError::NoMemory => c::munge_err_EMUNGE_NO_MEMORY,
+            MungeError::Socket => c::munge_err_EMUNGE_SOCKET,
+            MungeError::Timeout => c::munge_err_EMUNGE_TIMEOUT,
+            MungeError::BadCred => c::munge_err_EMUNGE_BAD_CRED,
+            MungeError::BadVersion => c::munge_err_EMUNGE_BAD_VERSION,
+            MungeError::BadCipher => c::munge_err_EMUNGE_BAD_CIPHER,
+            MungeError::BadMac => c::munge_err_EMUNGE_BAD_MAC,
+            MungeError::BadZip => c::munge_err_EMUNGE_BAD_ZIP,
+            MungeError::BadRealm => c::munge_err_EMUNGE_BAD_REALM,
+            MungeError::CredInvalid => c::munge_err_EMUNGE_CRED_INVALID,
+            MungeError::CredExpired => c::munge_err_EMUNGE_CRED_EXPIRED,
+            MungeError::CredRewound => c::munge_err_EMUNGE_CRED_REWOUND,
+            MungeError::CredReplayed => c::munge_err_EMUNGE_CRED_REPLAYED,
+            MungeError::CredUnauthorized => c::munge_err_EMUNGE_CRED_UNAUTHORIZED,
+            MungeError::Unknown(code) => code,
+        }
+    }
+
+    /// Checks whether the operation may succeed when tried again.
+    ///
+    /// This holds for failures to reach munged, which a [`crate::RetryPolicy`] retries.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, MungeError::Socket | MungeError::Timeout)
+    }
+
+    /// Checks whether munged decoded the credential but rejected it, ie. it is invalid,
+    /// expired, rewound, replayed or not meant for the caller.
+    pub fn is_credential_rejection(&self) -> bool {
+        matches!(
+            self,
+            MungeError::CredInvalid
+                | MungeError::CredExpired
+                | MungeError::CredRewound
+                | MungeError::CredReplayed
+                | MungeError::CredUnauthorized
+        )
+    }
+
+    /// Checks whether the communication with munged failed.
+    pub fn is_transport_error(&self) -> bool {
+        matches!(self, MungeError::Socket | MungeError::Timeout)
+    }
+}
+
+impl TryFrom<u32> for MungeError {
+    type Error = u32;
+
+    /// Converts a raw error code into a known `MungeError`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the code itself if it is `EMUNGE_SUCCESS` or not known to this crate.
+    fn try_from(err: u32) -> Result<Self, Self::Error> {
+        Ok(match err {
             c::munge_err_EMUNGE_SNAFU => MungeError::Snafu,
             c::munge_err_EMUNGE_BAD_ARG => MungeError::BadArg,
             c::munge_err_EMUNGE_BAD_LENGTH => MungeError::BadLength,
@@ -122,8 +219,8 @@ impl MungeError {
             c::munge_err_EMUNGE_CRED_REWOUND => MungeError::CredRewound,
             c::munge_err_EMUNGE_CRED_REPLAYED => MungeError::CredReplayed,
             c::munge_err_EMUNGE_CRED_UNAUTHORIZED => MungeError::CredUnauthorized,
-            _ => MungeError::BadArg,
-        }
+            _ => return Err(err),
+        })
     }
 }
 
@@ -135,6 +232,14 @@ pub enum Error {
     #[error("Munge errored: {0}, {1}")]
     MungeError(MungeError, String),
 
+    /// A credential that was decoded but rejected by the MUNGE library because it
+    /// has expired, was rewound or was replayed.
+    ///
+    /// libmunge still reports the UID, GID, payload and metadata of such credentials,
+    /// they are carried here so the caller can decide how to handle them.
+    #[error("Munge rejected credential of UID {uid} GID {gid}: {0}, {1}", uid = .2.uid, gid = .2.gid)]
+    CredentialRejected(MungeError, String, DecodedCredential<Vec<u8>>),
+
     /// An error indicating that a C string could not be converted to a valid UTF-8 Rust string.
     #[error("C string to Rust string lift failed: got non-UTF8 output: {0}")]
     InvalidUtf8(#[from] Utf8Error),
@@ -164,6 +269,101 @@ pub enum Error {
     TryFromPrimitiveZip(#[from] TryFromPrimitiveError<MungeZip>),
     #[error("Time is out of range or invalid nanosecond")]
     InvalidTime,
+
+    /// An error indicating that a typed payload could not be serialized into a credential
+    /// or deserialized from one.
+    #[error("Payload (de)serialization failed: {0}")]
+    Serialization(Box<dyn std::error::Error + Send + Sync>),
+
+    /// An error indicating that a cipher, MAC or compression type name is not known.
+    #[error("Unknown {0} type: {1}")]
+    UnknownName(&'static str, String),
+
+    /// An error indicating that a context setting from the environment or a
+    /// configuration file is invalid.
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    /// An error indicating that an operation kept failing to reach munged and the
+    /// [`crate::RetryPolicy`] gave up after the given number of attempts.
+    #[error("Giving up after {0} attempts: {1}")]
+    RetriesExhausted(u32, Box<Error>),
+
+    /// A credential that munged accepted but that was already accepted before, as
+    /// recorded by a `ReplayCache`.
+    #[error("Credential of UID {uid} GID {gid} was already used", uid = .0.uid, gid = .0.gid)]
+    ReplayDetected(DecodedCredential<Vec<u8>>),
+
+    /// A credential that munged accepted but that a `Policy` rejected for the given
+    /// reason.
+    #[error("Policy rejected credential of UID {uid} GID {gid}: {0}", uid = .1.uid, gid = .1.gid)]
+    PolicyViolation(crate::PolicyViolation, DecodedCredential<Vec<u8>>),
+
+    /// An I/O error, eg. while reading or writing a file.
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// An error indicating that the peer of a handshake did not follow the protocol.
+    #[error("Handshake failed: {0}")]
+    Handshake(&'static str),
+
+    /// An error indicating that the response to a `Challenge` does not answer it.
+    #[error("Challenge failed: {0}")]
+    Challenge(&'static str),
+
+    /// An error indicating that an `Envelope` is malformed or was rejected by its claims.
+    #[error("Envelope rejected: {0}")]
+    Envelope(&'static str),
+}
+
+impl Error {
+    /// Returns the [`MungeError`] carried by this error, if any.
+    pub fn munge_error(&self) -> Option<&MungeError> {
+        match self {
+            Error::MungeError(e, _) | Error::CredentialRejected(e, _, _) => Some(e),
+            Error::RetriesExhausted(_, e) => e.munge_error(),
+            _ => None,
+        }
+    }
+
+    /// Checks whether the error carries a [`MungeError`] that may go away when the
+    /// operation is tried again, see [`MungeError::is_retryable`].
+    ///
+    /// This is false for [`Error::RetriesExhausted`], as the retries already failed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::RetriesExhausted(..) => false,
+            _ => self.munge_error().is_some_and(MungeError::is_retryable),
+        }
+    }
+
+    /// Checks whether munged rejected the credential, see
+    /// [`MungeError::is_credential_rejection`].
+    pub fn is_credential_rejection(&self) -> bool {
+        self.munge_error()
+            .is_some_and(MungeError::is_credential_rejection)
+    }
+
+    /// Checks whether the communication with munged failed, see
+    /// [`MungeError::is_transport_error`].
+    pub fn is_transport_error(&self) -> bool {
+        self.munge_error()
+            .is_some_and(MungeError::is_transport_error)
+    }
+
+    /// Returns the partially decoded credential of a rejected credential.
+    ///
+    /// This is only available for [`Error::CredentialRejected`], ie. when the credential
+    /// has expired, was rewound or was replayed, for [`Error::ReplayDetected`] and for
+    /// [`Error::PolicyViolation`].
+    pub fn rejected_credential(&self) -> Option<&DecodedCredential<Vec<u8>>> {
+        match self {
+            Error::CredentialRejected(_, _, cred)
+            | Error::ReplayDetected(cred)
+            | Error::PolicyViolation(_, cred) => Some(cred),
+            _ => None,
+        }
+    }
 }
 
 /// Symmetric cipher types.
@@ -206,3 +406,220 @@ pub enum MungeZip {
     Bzlib = c::munge_zip_MUNGE_ZIP_BZLIB,
     Zlib = c::munge_zip_MUNGE_ZIP_ZLIB,
 }
+
+impl MungeCipher {
+    const ALL: [MungeCipher; 6] = [
+        MungeCipher::None,
+        MungeCipher::Default,
+        MungeCipher::Blowfish,
+        MungeCipher::Cast5,
+        MungeCipher::Aes128,
+        MungeCipher::Aes256,
+    ];
+
+    /// Checks whether the cipher type is supported by the local libmunge build.
+    ///
+    /// With the `pure-rust` feature every cipher type is reported as supported, since
+    /// only munged knows what it was built with.
+    pub fn is_supported(self) -> bool {
+        sys::enum_is_valid(MungeEnum::Cipher, self as u32)
+    }
+
+    /// Returns an iterator over the supported cipher types.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let names: Vec<String> = MungeCipher::supported().map(|c| c.to_string()).collect();
+    /// ```
+    pub fn supported() -> impl Iterator<Item = MungeCipher> {
+        Self::ALL.into_iter().filter(|cipher| cipher.is_supported())
+    }
+}
+
+impl MungeMac {
+    const ALL: [MungeMac; 7] = [
+        MungeMac::None,
+        MungeMac::Default,
+        MungeMac::MD5,
+        MungeMac::SHA1,
+        MungeMac::RIPEMD160,
+        MungeMac::SHA256,
+        MungeMac::SHA512,
+    ];
+
+    /// Checks whether the MAC type is supported by the local libmunge build.
+    ///
+    /// With the `pure-rust` feature every MAC type is reported as supported, since only
+    /// munged knows what it was built with.
+    pub fn is_supported(self) -> bool {
+        sys::enum_is_valid(MungeEnum::Mac, self as u32)
+    }
+
+    /// Returns an iterator over the supported MAC types.
+    pub fn supported() -> impl Iterator<Item = MungeMac> {
+        Self::ALL.into_iter().filter(|mac| mac.is_supported())
+    }
+}
+
+impl MungeZip {
+    const ALL: [MungeZip; 4] = [
+        MungeZip::None,
+        MungeZip::Default,
+        MungeZip::Bzlib,
+        MungeZip::Zlib,
+    ];
+
+    /// Checks whether the compression type is supported by the local libmunge build.
+    ///
+    /// With the `pure-rust` feature every compression type is reported as supported,
+    /// since only munged knows what it was built with.
+    pub fn is_supported(self) -> bool {
+        sys::enum_is_valid(MungeEnum::Zip, self as u32)
+    }
+
+    /// Returns an iterator over the supported compression types.
+    pub fn supported() -> impl Iterator<Item = MungeZip> {
+        Self::ALL.into_iter().filter(|zip| zip.is_supported())
+    }
+}
+
+/// Formats the type with its libmunge name, eg. `aes128`.
+impl fmt::Display for MungeCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_name(f, MungeEnum::Cipher, *self as u32)
+    }
+}
+
+/// Formats the type with its libmunge name, eg. `sha256`.
+impl fmt::Display for MungeMac {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_name(f, MungeEnum::Mac, *self as u32)
+    }
+}
+
+/// Formats the type with its libmunge name, eg. `zlib`.
+impl fmt::Display for MungeZip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_name(f, MungeEnum::Zip, *self as u32)
+    }
+}
+
+impl FromStr for MungeCipher {
+    type Err = Error;
+
+    /// Parses a cipher type from its libmunge name, ignoring case, or from its number.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        from_name(MungeEnum::Cipher, s)
+    }
+}
+
+impl FromStr for MungeMac {
+    type Err = Error;
+
+    /// Parses a MAC type from its libmunge name, ignoring case, or from its number.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        from_name(MungeEnum::Mac, s)
+    }
+}
+
+impl FromStr for MungeZip {
+    type Err = Error;
+
+    /// Parses a compression type from its libmunge name, ignoring case, or from its
+    /// number.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        from_name(MungeEnum::Zip, s)
+    }
+}
+
+/// Serializes cipher, MAC and compression types by name, deserializing them with
+/// [`FromStr`] so numbers are accepted too.
+#[cfg(feature = "serde")]
+macro_rules! serde_by_name {
+    ($($ty:ty),*) => {$(
+        impl serde::Serialize for $ty {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $ty {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let name = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+                name.parse().map_err(serde::de::Error::custom)
+            }
+        }
+    )*};
+}
+
+#[cfg(feature = "serde")]
+serde_by_name!(MungeCipher, MungeMac, MungeZip);
+
+fn write_name(f: &mut fmt::Formatter<'_>, kind: MungeEnum, value: u32) -> fmt::Result {
+    match sys::enum_int_to_str(kind, value).and_then(|name| name.to_str().ok()) {
+        Some(name) => f.write_str(name),
+        None => write!(f, "{value}"),
+    }
+}
+
+fn from_name<T: TryFromPrimitive<Primitive = u32>>(kind: MungeEnum, s: &str) -> Result<T, Error> {
+    CString::new(s.trim())
+        .ok()
+        .and_then(|name| sys::enum_str_to_int(kind, &name))
+        .and_then(|value| T::try_from_primitive(value).ok())
+        .ok_or_else(|| Error::UnknownName(kind.label(), s.to_string()))
+}
+
+#[cfg(test)]
+mod enums_tests {
+    use crate::enums::{Error, MungeCipher, MungeError, MungeMac, MungeZip};
+
+    #[test]
+    fn names_round_trip() {
+        for cipher in MungeCipher::supported() {
+            assert_eq!(cipher.to_string().parse::<MungeCipher>().unwrap(), cipher);
+        }
+        for mac in MungeMac::supported() {
+            assert_eq!(mac.to_string().parse::<MungeMac>().unwrap(), mac);
+        }
+        for zip in MungeZip::supported() {
+            assert_eq!(zip.to_string().parse::<MungeZip>().unwrap(), zip);
+        }
+    }
+
+    #[test]
+    fn parse_names() {
+        assert_eq!(MungeCipher::Aes128.to_string(), "aes128");
+        assert_eq!("SHA256".parse::<MungeMac>().unwrap(), MungeMac::SHA256);
+        assert_eq!("3".parse::<MungeZip>().unwrap(), MungeZip::Zlib);
+        assert!(matches!(
+            "rot13".parse::<MungeCipher>(),
+            Err(Error::UnknownName("cipher", _))
+        ));
+        assert!(MungeCipher::supported().any(|c| c == MungeCipher::None));
+    }
+
+    #[test]
+    fn error_codes() {
+        assert_eq!(MungeError::from_u32(15), MungeError::CredExpired);
+        assert_eq!(MungeError::CredExpired.code(), 15);
+        assert_eq!(MungeError::from_u32(99), MungeError::Unknown(99));
+        assert_eq!(MungeError::Unknown(99).code(), 99);
+        assert_eq!(MungeError::try_from(0), Err(0));
+        assert_eq!(MungeError::try_from(99), Err(99));
+
+        assert!(MungeError::Socket.is_retryable());
+        assert!(MungeError::Socket.is_transport_error());
+        assert!(!MungeError::CredReplayed.is_retryable());
+        assert!(MungeError::CredReplayed.is_credential_rejection());
+        assert!(!MungeError::Unknown(99).is_retryable());
+        assert!(!MungeError::NoMemory.is_retryable());
+
+        let socket = Error::MungeError(MungeError::Socket, String::new());
+        assert!(socket.is_retryable());
+        let exhausted = Error::RetriesExhausted(3, Box::new(socket));
+        assert!(!exhausted.is_retryable());
+        assert!(exhausted.is_transport_error());
+    }
+}
@@ -0,0 +1,440 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    backend::{MungeBackend, Munged},
+    credential::Credential,
+    ctx::Context,
+    enums::Error,
+};
+
+/// First line of every envelope, including the format version.
+const VERSION_LINE: &str = "MUNGE-ENVELOPE/1";
+
+/// How far in the future `iat` may lie, to tolerate clocks drifting between nodes.
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(10);
+
+/// Payload format shared by services, carrying claims about the credential next to an
+/// opaque body.
+///
+/// The payload starts with a header of `key=value` lines, ended by an empty line and
+/// followed by the body:
+///
+/// ```text
+/// MUNGE-ENVELOPE/1
+/// aud=scheduler
+/// purpose=submit-job
+/// iat=1700000000
+/// exp=1700000030
+///
+/// <body>
+/// ```
+///
+/// `iat` and `exp` are Unix timestamps in seconds. `exp` is optional and lets the sender
+/// limit the lifetime of the credential below the TTL of munged. It must not lie before
+/// `iat`.
+///
+/// # Example
+///
+/// ```ignore
+/// let envelope = Envelope::new("scheduler", "submit-job", job.as_bytes())
+///     .expires_in(Duration::from_secs(30));
+/// let encoded = encode_envelope(&envelope, None)?;
+///
+/// let cred = verify_envelope(&encoded, "scheduler", "submit-job", None)?;
+/// println!("UID {} submitted {} bytes", cred.uid, cred.message.len());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    /// Service the credential is meant for.
+    pub audience: String,
+    /// What the credential is meant to be used for, eg. the requested operation.
+    pub purpose: String,
+    /// Time the envelope was created.
+    pub issued_at: DateTime<Utc>,
+    /// Time after which the envelope is rejected, if any.
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Opaque application data.
+    pub body: Vec<u8>,
+}
+
+impl Envelope {
+    /// Creates an envelope issued now, without expiry.
+    pub fn new(
+        audience: impl Into<String>,
+        purpose: impl Into<String>,
+        body: impl Into<Vec<u8>>,
+    ) -> Self {
+        Envelope {
+            audience: audience.into(),
+            purpose: purpose.into(),
+            issued_at: Utc::now(),
+            expires_at: None,
+            body: body.into(),
+        }
+    }
+
+    /// Sets the envelope to expire the given time after it was issued.
+    pub fn expires_in(mut self, lifetime: Duration) -> Self {
+        self.expires_at = Some(self.issued_at + lifetime);
+        self
+    }
+
+    /// Checks that the envelope is meant for the given audience and purpose, was not
+    /// issued in the future and has not expired.
+    ///
+    /// An issue time up to 10 seconds in the future is accepted, to tolerate clocks
+    /// drifting between nodes.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Envelope`] if the audience or purpose differ, the envelope was
+    /// issued in the future, expires before it was issued or expired.
+    pub fn check(&self, audience: &str, purpose: &str) -> Result<(), Error> {
+        self.check_at(audience, purpose, Utc::now())
+    }
+
+    fn check_at(&self, audience: &str, purpose: &str, now: DateTime<Utc>) -> Result<(), Error> {
+        if self.audience != audience {
+            return Err(Error::Envelope("Envelope is meant for another audience."));
+        }
+        if self.purpose != purpose {
+            return Err(Error::Envelope("Envelope is meant for another purpose."));
+        }
+        if self.issued_at > now + MAX_CLOCK_SKEW {
+            return Err(Error::Envelope("Envelope was issued in the future."));
+        }
+        self.check_expiry()?;
+        if self.expires_at.is_some_and(|exp| now > exp) {
+            return Err(Error::Envelope("Envelope expired."));
+        }
+        Ok(())
+    }
+
+    fn check_expiry(&self) -> Result<(), Error> {
+        if self.expires_at.is_some_and(|exp| exp < self.issued_at) {
+            return Err(Error::Envelope("Envelope expires before it was issued."));
+        }
+        Ok(())
+    }
+
+    /// Checks whether the payload claims to be an envelope, of any version.
+    pub(crate) fn is_envelope(payload: &[u8]) -> bool {
+        payload.starts_with(b"MUNGE-ENVELOPE/")
+    }
+
+    /// Serializes the envelope into a credential payload.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Envelope`] if the audience or purpose contain a newline, or the
+    /// envelope expires before it was issued.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        if self.audience.contains('\n') || self.purpose.contains('\n') {
+            return Err(Error::Envelope(
+                "Audience and purpose must not contain newlines.",
+            ));
+        }
+        self.check_expiry()?;
+
+        let mut header = format!(
+            "{VERSION_LINE}\naud={}\npurpose={}\niat={}\n",
+            self.audience,
+            self.purpose,
+            self.issued_at.timestamp()
+        );
+        if let Some(exp) = self.expires_at {
+            header.push_str(&format!("exp={}\n", exp.timestamp()));
+        }
+        header.push('\n');
+
+        let mut payload = header.into_bytes();
+        payload.extend_from_slice(&self.body);
+        Ok(payload)
+    }
+
+    /// Parses an envelope from a credential payload.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Envelope`] if the payload is not an envelope of a supported
+    /// version.
+    pub fn from_bytes(payload: &[u8]) -> Result<Self, Error> {
+        let malformed = || Error::Envelope("Payload is not a valid envelope.");
+
+        let end = payload
+            .windows(2)
+            .position(|w| w == b"\n\n")
+            .ok_or_else(malformed)?;
+        let header = std::str::from_utf8(&payload[..end]).map_err(|_| malformed())?;
+        let body = payload[end + 2..].to_vec();
+
+        let mut lines = header.split('\n');
+        if lines.next() != Some(VERSION_LINE) {
+            return Err(Error::Envelope("Unsupported envelope version."));
+        }
+
+        let (mut aud, mut purpose, mut iat, mut exp) = (None, None, None, None);
+        for line in lines {
+            let (key, value) = line.split_once('=').ok_or_else(malformed)?;
+            let field = match key {
+                "aud" => &mut aud,
+                "purpose" => &mut purpose,
+                "iat" => &mut iat,
+                "exp" => &mut exp,
+                _ => return Err(malformed()),
+            };
+            // Reject duplicates instead of guessing which one the sender meant.
+            if field.replace(value).is_some() {
+                return Err(malformed());
+            }
+        }
+
+        let timestamp = |value: &str| {
+            value
+                .parse()
+                .ok()
+                .and_then(|secs| DateTime::from_timestamp(secs, 0))
+                .ok_or_else(malformed)
+        };
+        Ok(Envelope {
+            audience: aud.ok_or_else(malformed)?.to_string(),
+            purpose: purpose.ok_or_else(malformed)?.to_string(),
+            issued_at: timestamp(iat.ok_or_else(malformed)?)?,
+            expires_at: exp.map(timestamp).transpose()?,
+            body,
+        })
+    }
+}
+
+/// Encodes an [`Envelope`] into a credential.
+///
+/// # Arguments
+///
+/// * `envelope` - The envelope to encode.
+/// * `ctx` - An optional reference to a [`Context`]. If no context is provided, defaults will be used.
+///
+/// # Errors
+///
+/// Returns the errors of [`Envelope::to_bytes`] and [`crate::encode_bytes`].
+pub fn encode_envelope(envelope: &Envelope, ctx: Option<&Context>) -> Result<String, Error> {
+    encode_envelope_with(&Munged, envelope, ctx)
+}
+
+/// Encodes an [`Envelope`] into a credential with the given backend.
+///
+/// # Errors
+///
+/// Returns the errors of [`Envelope::to_bytes`] and of the backend.
+pub fn encode_envelope_with<B: MungeBackend + ?Sized>(
+    backend: &B,
+    envelope: &Envelope,
+    ctx: Option<&Context>,
+) -> Result<String, Error> {
+    backend.encode_bytes(&envelope.to_bytes()?, ctx)
+}
+
+/// Decodes a credential carrying an [`Envelope`] without checking its claims.
+///
+/// # Arguments
+///
+/// * `encoded_msg` - The credential to decode.
+/// * `ctx` - An optional reference to a [`Context`]. If no context is provided, defaults will be used.
+///
+/// # Errors
+///
+/// Returns the errors of [`crate::decode_bytes`] and [`Envelope::from_bytes`].
+pub fn decode_envelope(
+    encoded_msg: &str,
+    ctx: Option<&Context>,
+) -> Result<Credential<Envelope>, Error> {
+    decode_envelope_with(&Munged, encoded_msg, ctx)
+}
+
+/// Decodes a credential carrying an [`Envelope`] with the given backend without
+/// checking its claims.
+///
+/// # Errors
+///
+/// Returns the errors of the backend and of [`Envelope::from_bytes`].
+pub fn decode_envelope_with<B: MungeBackend + ?Sized>(
+    backend: &B,
+    encoded_msg: &str,
+    ctx: Option<&Context>,
+) -> Result<Credential<Envelope>, Error> {
+    let cred = backend.decode_bytes(encoded_msg, ctx)?;
+    Ok(Credential {
+        uid: cred.uid,
+        gid: cred.gid,
+        message: Envelope::from_bytes(&cred.message)?,
+    })
+}
+
+/// Decodes a credential carrying an [`Envelope`] and checks its claims, see
+/// [`Envelope::check`].
+///
+/// # Arguments
+///
+/// * `encoded_msg` - The credential to decode.
+/// * `audience` - The audience the envelope must name, typically the name of the
+///   verifying service.
+/// * `purpose` - The purpose the envelope must name, eg. the requested operation.
+/// * `ctx` - An optional reference to a [`Context`]. If no context is provided, defaults will be used.
+///
+/// # Errors
+///
+/// Returns the errors of [`decode_envelope`] and [`Envelope::check`].
+///
+/// # Returns
+///
+/// The UID and GID of the sender with the body of the envelope.
+pub fn verify_envelope(
+    encoded_msg: &str,
+    audience: &str,
+    purpose: &str,
+    ctx: Option<&Context>,
+) -> Result<Credential<Vec<u8>>, Error> {
+    verify_envelope_with(&Munged, encoded_msg, audience, purpose, ctx)
+}
+
+/// Decodes a credential carrying an [`Envelope`] with the given backend and checks its
+/// claims, see [`verify_envelope`].
+///
+/// # Errors
+///
+/// Returns the errors of [`decode_envelope_with`] and [`Envelope::check`].
+pub fn verify_envelope_with<B: MungeBackend + ?Sized>(
+    backend: &B,
+    encoded_msg: &str,
+    audience: &str,
+    purpose: &str,
+    ctx: Option<&Context>,
+) -> Result<Credential<Vec<u8>>, Error> {
+    let cred = decode_envelope_with(backend, encoded_msg, ctx)?;
+    cred.message.check(audience, purpose)?;
+
+    Ok(Credential {
+        uid: cred.uid,
+        gid: cred.gid,
+        message: cred.message.body,
+    })
+}
+
+#[cfg(test)]
+mod envelope_tests {
+    use std::time::Duration;
+
+    use chrono::DateTime;
+
+    use crate::{
+        enums::Error,
+        envelope::{encode_envelope_with, verify_envelope_with, Envelope},
+        mock::MockBackend,
+    };
+
+    #[test]
+    fn round_trip() {
+        let envelope = Envelope {
+            issued_at: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            ..Envelope::new("scheduler", "submit-job", b"\n\nbody\n".to_vec())
+        }
+        .expires_in(Duration::from_secs(30));
+
+        let payload = envelope.to_bytes().unwrap();
+        assert!(payload.starts_with(
+            b"MUNGE-ENVELOPE/1\naud=scheduler\npurpose=submit-job\niat=1700000000\nexp=1700000030\n\n"
+        ));
+        assert_eq!(Envelope::from_bytes(&payload).unwrap(), envelope);
+
+        let plain = Envelope::new("scheduler", "ping", Vec::new());
+        let parsed = Envelope::from_bytes(&plain.to_bytes().unwrap()).unwrap();
+        assert_eq!(parsed.expires_at, None);
+        assert!(parsed.body.is_empty());
+    }
+
+    #[test]
+    fn malformed() {
+        let invalid =
+            |payload: &[u8]| matches!(Envelope::from_bytes(payload), Err(Error::Envelope(_)));
+
+        assert!(invalid(b"{\"aud\": \"scheduler\"}"));
+        assert!(invalid(b"MUNGE-ENVELOPE/2\naud=a\npurpose=b\niat=0\n\n"));
+        assert!(invalid(b"MUNGE-ENVELOPE/1\naud=a\npurpose=b\n\n"));
+        assert!(invalid(
+            b"MUNGE-ENVELOPE/1\naud=a\naud=b\npurpose=b\niat=0\n\n"
+        ));
+        assert!(invalid(b"MUNGE-ENVELOPE/1\naud=a\npurpose=b\niat=now\n\n"));
+        assert!(Envelope::new("a\nb", "c", Vec::new()).to_bytes().is_err());
+
+        let backwards = Envelope {
+            expires_at: DateTime::from_timestamp(1_600_000_000, 0),
+            ..Envelope::new("a", "b", Vec::new())
+        };
+        assert!(matches!(backwards.to_bytes(), Err(Error::Envelope(_))));
+    }
+
+    #[test]
+    fn check() {
+        let envelope = Envelope::new("scheduler", "submit-job", Vec::new());
+        let now = envelope.issued_at;
+        let rejected = |envelope: &Envelope, now| {
+            matches!(
+                envelope.check_at("scheduler", "submit-job", now),
+                Err(Error::Envelope(_))
+            )
+        };
+
+        assert!(envelope.check("scheduler", "submit-job").is_ok());
+        assert!(matches!(
+            envelope.check("worker", "submit-job"),
+            Err(Error::Envelope(_))
+        ));
+        assert!(matches!(
+            envelope.check("scheduler", "cancel-job"),
+            Err(Error::Envelope(_))
+        ));
+
+        // Issued in the future, within and beyond the clock skew.
+        assert!(envelope
+            .check_at("scheduler", "submit-job", now - Duration::from_secs(5))
+            .is_ok());
+        assert!(rejected(&envelope, now - Duration::from_secs(11)));
+
+        let expiring = Envelope {
+            issued_at: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            ..envelope
+        }
+        .expires_in(Duration::from_secs(30));
+        let issued = expiring.issued_at;
+        assert!(expiring
+            .check_at("scheduler", "submit-job", issued + Duration::from_secs(30))
+            .is_ok());
+        assert!(rejected(&expiring, issued + Duration::from_secs(31)));
+
+        let backwards = Envelope {
+            expires_at: Some(issued - Duration::from_secs(1)),
+            ..expiring
+        };
+        assert!(rejected(&backwards, issued - Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn verify() {
+        let mock = MockBackend::new().with_uid(1000);
+        let envelope = Envelope::new("scheduler", "submit-job", b"job".to_vec())
+            .expires_in(Duration::from_secs(30));
+        let encoded = encode_envelope_with(&mock, &envelope, None).expect("Failed to encode");
+
+        let cred = verify_envelope_with(&mock, &encoded, "scheduler", "submit-job", None)
+            .expect("Failed to verify");
+        assert_eq!(cred.uid, 1000);
+        assert_eq!(cred.message, b"job");
+
+        let encoded = encode_envelope_with(&mock, &envelope, None).expect("Failed to encode");
+        assert!(matches!(
+            verify_envelope_with(&mock, &encoded, "scheduler", "cancel-job", None),
+            Err(Error::Envelope(_))
+        ));
+    }
+}
@@ -0,0 +1,268 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    backend::{MungeBackend, Munged},
+    credential::DecodedCredential,
+    ctx::{Context, ContextBuilder},
+    enums::{Error, MungeError},
+};
+
+/// Result of a request served by a [`FailoverContext`], together with the socket of the
+/// munged that served it.
+#[derive(Debug, Clone)]
+pub struct Served<T> {
+    /// Value returned by munged.
+    pub value: T,
+    /// Socket of the munged that served the request.
+    pub socket: PathBuf,
+}
+
+/// Context holding an ordered list of munged sockets, eg. a primary and a fallback daemon.
+///
+/// Each request is first sent to the last socket that served a request, starting with the
+/// first socket of the list. When that socket fails with [`MungeError::Socket`], the
+/// request is sent to the next socket in order, wrapping around, until one serves it.
+/// Any other error, eg. a rejected credential, is returned right away.
+///
+/// All sockets share the options of the [`ContextBuilder`] the context was created from,
+/// including its retry policy, which is applied per socket before failing over.
+///
+/// The context can be shared between threads. The metadata of each decoded credential
+/// is the one reported for that request, even when other threads decode through the
+/// same socket at the same time.
+///
+/// Note that credentials can only be decoded by a munged sharing the key of the munged
+/// that encoded them.
+///
+/// # Example
+///
+/// ```ignore
+/// let ctx = FailoverContext::new([
+///     "/run/munge/munge.socket.2",
+///     "/run/munge-fallback/munge.socket.2",
+/// ])?;
+/// let served = ctx.encode("payload")?;
+/// println!("Encoded by {}: {}", served.socket.display(), served.value);
+/// ```
+#[derive(Debug)]
+pub struct FailoverContext {
+    contexts: Vec<(PathBuf, Context)>,
+    healthy: AtomicUsize,
+}
+
+impl FailoverContext {
+    /// Creates a context with default options for the given sockets.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`FailoverContext::with_builder`].
+    pub fn new<P: Into<PathBuf>>(sockets: impl IntoIterator<Item = P>) -> Result<Self, Error> {
+        Self::with_builder(&Context::builder(), sockets)
+    }
+
+    /// Creates a context for the given sockets with the options of `builder`.
+    ///
+    /// The socket set on the builder, if any, is replaced by each of the sockets.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidConfig`] if no socket is given, or the errors of
+    /// [`ContextBuilder::build`].
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let builder = Context::builder().ttl(60).retry_policy(RetryPolicy::new(2));
+    /// let ctx = FailoverContext::with_builder(&builder, [primary, fallback])?;
+    /// ```
+    pub fn with_builder<P: Into<PathBuf>>(
+        builder: &ContextBuilder,
+        sockets: impl IntoIterator<Item = P>,
+    ) -> Result<Self, Error> {
+        let contexts = sockets
+            .into_iter()
+            .map(|socket| {
+                let socket = socket.into();
+                let ctx = builder.clone().socket(&socket).build()?;
+                Ok((socket, ctx))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        if contexts.is_empty() {
+            return Err(Error::InvalidConfig(
+                "At least one munged socket is required.".to_string(),
+            ));
+        }
+
+        Ok(FailoverContext {
+            contexts,
+            healthy: AtomicUsize::new(0),
+        })
+    }
+
+    /// Returns the sockets in the order they are tried.
+    pub fn sockets(&self) -> impl Iterator<Item = &Path> {
+        self.contexts.iter().map(|(socket, _)| socket.as_path())
+    }
+
+    /// Returns the socket that served the last request, or the first socket if no
+    /// request was served yet.
+    pub fn healthy_socket(&self) -> &Path {
+        &self.contexts[self.healthy.load(Ordering::Relaxed)].0
+    }
+
+    /// Returns the context used for the given socket, eg. to read its options.
+    pub fn context(&self, socket: impl AsRef<Path>) -> Option<&Context> {
+        self.contexts
+            .iter()
+            .find(|(path, _)| path == socket.as_ref())
+            .map(|(_, ctx)| ctx)
+    }
+
+    /// Encodes the given message and returns a credential string.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the last socket tried if no munged could encode the message.
+    pub fn encode(&self, msg: &str) -> Result<Served<String>, Error> {
+        self.encode_bytes(msg.as_bytes())
+    }
+
+    /// Encodes the given binary payload and returns a credential string.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the last socket tried if no munged could encode the payload.
+    pub fn encode_bytes(&self, payload: &[u8]) -> Result<Served<String>, Error> {
+        self.encode_bytes_with(&Munged, payload)
+    }
+
+    /// Encodes the given binary payload with the given backend, passing it the context
+    /// of each socket in turn.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the last socket tried if the backend could not encode the
+    /// payload for any of them.
+    pub fn encode_bytes_with<B: MungeBackend + ?Sized>(
+        &self,
+        backend: &B,
+        payload: &[u8],
+    ) -> Result<Served<String>, Error> {
+        self.failover(|ctx| backend.encode_bytes(payload, Some(ctx)))
+    }
+
+    /// Decodes the credential string into a credential with a UTF-8 message and its
+    /// metadata.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the last socket tried if no munged could decode the
+    /// credential, or an error if the payload is not valid UTF-8.
+    pub fn decode(&self, encoded_msg: &str) -> Result<Served<DecodedCredential>, Error> {
+        let served = self.decode_bytes(encoded_msg)?;
+        Ok(Served {
+            value: DecodedCredential::try_from(served.value)?,
+            socket: served.socket,
+        })
+    }
+
+    /// Decodes the credential string into a credential with a binary payload and its
+    /// metadata.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the last socket tried if no munged could decode the
+    /// credential. Expired, rewound and replayed credentials are reported as
+    /// [`Error::CredentialRejected`] without trying other sockets.
+    pub fn decode_bytes(
+        &self,
+        encoded_msg: &str,
+    ) -> Result<Served<DecodedCredential<Vec<u8>>>, Error> {
+        self.decode_bytes_with(&Munged, encoded_msg)
+    }
+
+    /// Decodes the credential string with the given backend, passing it the context of
+    /// each socket in turn.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the last socket tried if the backend could not decode the
+    /// credential for any of them.
+    pub fn decode_bytes_with<B: MungeBackend + ?Sized>(
+        &self,
+        backend: &B,
+        encoded_msg: &str,
+    ) -> Result<Served<DecodedCredential<Vec<u8>>>, Error> {
+        self.failover(|ctx| backend.decode_bytes(encoded_msg, Some(ctx)))
+    }
+
+    /// Runs the request on each socket in turn, starting with the healthy one, until it
+    /// does not fail with a socket error.
+    fn failover<T>(&self, op: impl Fn(&Context) -> Result<T, Error>) -> Result<Served<T>, Error> {
+        let start = self.healthy.load(Ordering::Relaxed);
+        let mut last_err = None;
+
+        for i in (start..self.contexts.len()).chain(0..start) {
+            let (socket, ctx) = &self.contexts[i];
+            match op(ctx) {
+                Err(e) if e.munge_error() == Some(&MungeError::Socket) => last_err = Some(e),
+                res => {
+                    self.healthy.store(i, Ordering::Relaxed);
+                    return res.map(|value| Served {
+                        value,
+                        socket: socket.clone(),
+                    });
+                }
+            }
+        }
+
+        Err(last_err.expect("FailoverContext has at least one socket"))
+    }
+}
+
+#[cfg(test)]
+mod failover_tests {
+    use std::path::Path;
+
+    use crate::{
+        enums::{Error, MungeError},
+        failover::FailoverContext,
+        mock::MockBackend,
+    };
+
+    #[test]
+    fn no_sockets() {
+        let res = FailoverContext::new(Vec::<String>::new());
+        assert!(matches!(res, Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn reports_socket() {
+        let mock = MockBackend::new();
+        let ctx = FailoverContext::new(["/run/primary.socket", "/run/fallback.socket"]).unwrap();
+        let fallback = Path::new("/run/fallback.socket");
+
+        mock.fail_next(MungeError::Socket);
+        let served = ctx
+            .encode_bytes_with(&mock, b"Failover")
+            .expect("Failed to encode");
+        assert_eq!(served.socket, fallback);
+        assert_eq!(ctx.healthy_socket(), fallback);
+
+        let decoded = ctx
+            .decode_bytes_with(&mock, &served.value)
+            .expect("Failed to decode");
+        assert_eq!(decoded.value.message, b"Failover");
+        assert_eq!(decoded.socket, fallback);
+
+        // Other errors are returned without failing over.
+        mock.fail_next(MungeError::CredReplayed);
+        let err = ctx.decode_bytes_with(&mock, &served.value).unwrap_err();
+        assert_eq!(err.munge_error(), Some(&MungeError::CredReplayed));
+        assert_eq!(ctx.healthy_socket(), fallback);
+    }
+}
@@ -1 +1,74 @@
+#[cfg(not(feature = "pure-rust"))]
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+
+/// Definitions from `munge.h` needed without libmunge, using the names bindgen generates.
+#[cfg(feature = "pure-rust")]
+mod defs {
+    pub type uid_t = u32;
+    pub type gid_t = u32;
+
+    pub type munge_opt = u32;
+    pub const munge_opt_MUNGE_OPT_CIPHER_TYPE: munge_opt = 0;
+    pub const munge_opt_MUNGE_OPT_MAC_TYPE: munge_opt = 1;
+    pub const munge_opt_MUNGE_OPT_ZIP_TYPE: munge_opt = 2;
+    pub const munge_opt_MUNGE_OPT_REALM: munge_opt = 3;
+    pub const munge_opt_MUNGE_OPT_TTL: munge_opt = 4;
+    pub const munge_opt_MUNGE_OPT_ADDR4: munge_opt = 5;
+    pub const munge_opt_MUNGE_OPT_ENCODE_TIME: munge_opt = 6;
+    pub const munge_opt_MUNGE_OPT_DECODE_TIME: munge_opt = 7;
+    pub const munge_opt_MUNGE_OPT_SOCKET: munge_opt = 8;
+    pub const munge_opt_MUNGE_OPT_UID_RESTRICTION: munge_opt = 9;
+    pub const munge_opt_MUNGE_OPT_GID_RESTRICTION: munge_opt = 10;
+
+    pub type munge_cipher = u32;
+    pub const munge_cipher_MUNGE_CIPHER_NONE: munge_cipher = 0;
+    pub const munge_cipher_MUNGE_CIPHER_DEFAULT: munge_cipher = 1;
+    pub const munge_cipher_MUNGE_CIPHER_BLOWFISH: munge_cipher = 2;
+    pub const munge_cipher_MUNGE_CIPHER_CAST5: munge_cipher = 3;
+    pub const munge_cipher_MUNGE_CIPHER_AES128: munge_cipher = 4;
+    pub const munge_cipher_MUNGE_CIPHER_AES256: munge_cipher = 5;
+
+    pub type munge_mac = u32;
+    pub const munge_mac_MUNGE_MAC_NONE: munge_mac = 0;
+    pub const munge_mac_MUNGE_MAC_DEFAULT: munge_mac = 1;
+    pub const munge_mac_MUNGE_MAC_MD5: munge_mac = 2;
+    pub const munge_mac_MUNGE_MAC_SHA1: munge_mac = 3;
+    pub const munge_mac_MUNGE_MAC_RIPEMD160: munge_mac = 4;
+    pub const munge_mac_MUNGE_MAC_SHA256: munge_mac = 5;
+    pub const munge_mac_MUNGE_MAC_SHA512: munge_mac = 6;
+
+    pub type munge_zip = u32;
+    pub const munge_zip_MUNGE_ZIP_NONE: munge_zip = 0;
+    pub const munge_zip_MUNGE_ZIP_DEFAULT: munge_zip = 1;
+    pub const munge_zip_MUNGE_ZIP_BZLIB: munge_zip = 2;
+    pub const munge_zip_MUNGE_ZIP_ZLIB: munge_zip = 3;
+
+    pub type munge_enum = u32;
+    pub const munge_enum_MUNGE_ENUM_CIPHER: munge_enum = 0;
+    pub const munge_enum_MUNGE_ENUM_MAC: munge_enum = 1;
+    pub const munge_enum_MUNGE_ENUM_ZIP: munge_enum = 2;
+
+    pub type munge_err = u32;
+    pub const munge_err_EMUNGE_SUCCESS: munge_err = 0;
+    pub const munge_err_EMUNGE_SNAFU: munge_err = 1;
+    pub const munge_err_EMUNGE_BAD_ARG: munge_err = 2;
+    pub const munge_err_EMUNGE_BAD_LENGTH: munge_err = 3;
+    pub const munge_err_EMUNGE_OVERFLOW: munge_err = 4;
+    pub const munge_err_EMUNGE_NO_MEMORY: munge_err = 5;
+    pub const munge_err_EMUNGE_SOCKET: munge_err = 6;
+    pub const munge_err_EMUNGE_TIMEOUT: munge_err = 7;
+    pub const munge_err_EMUNGE_BAD_CRED: munge_err = 8;
+    pub const munge_err_EMUNGE_BAD_VERSION: munge_err = 9;
+    pub const munge_err_EMUNGE_BAD_CIPHER: munge_err = 10;
+    pub const munge_err_EMUNGE_BAD_MAC: munge_err = 11;
+    pub const munge_err_EMUNGE_BAD_ZIP: munge_err = 12;
+    pub const munge_err_EMUNGE_BAD_REALM: munge_err = 13;
+    pub const munge_err_EMUNGE_CRED_INVALID: munge_err = 14;
+    pub const munge_err_EMUNGE_CRED_EXPIRED: munge_err = 15;
+    pub const munge_err_EMUNGE_CRED_REWOUND: munge_err = 16;
+    pub const munge_err_EMUNGE_CRED_REPLAYED: munge_err = 17;
+    pub const munge_err_EMUNGE_CRED_UNAUTHORIZED: munge_err = 18;
+}
+
+#[cfg(feature = "pure-rust")]
+pub use defs::*;
@@ -0,0 +1,378 @@
+use std::{
+    fmt,
+    sync::Arc,
+    task::{Context as TaskContext, Poll},
+    time::Duration,
+};
+
+use http::{
+    header::{HeaderName, CONTENT_TYPE},
+    HeaderValue, Request, Response,
+};
+use tonic::Status;
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{
+    backend::{MungeBackend, Munged},
+    ctx::Context,
+    middleware::{authenticate, insert_credential, BoxError, BoxFuture, Rejection},
+};
+
+/// Metadata key carrying the credential.
+pub const METADATA_KEY: &str = "x-munge-credential";
+
+/// [`Layer`] authenticating gRPC requests by the MUNGE credential in their metadata.
+///
+/// The credential must have been encoded by [`MungeGrpcClientLayer`], or otherwise carry
+/// the path of the called method, `/<package>.<Service>/<Method>`, as its payload. The
+/// layer decodes it with the shared [`Context`] by munged, or by the backend set with
+/// [`MungeGrpcAuthLayer::with_backend`], checks it against the path of the request and
+/// inserts it into the request extensions, where handlers find it with
+/// `req.extensions().get::<Credential>()`, or as a [`crate::RawCredential`] with
+/// `req.extensions().get::<RawCredential>()`. Requests are rejected with:
+/// - `UNAUTHENTICATED` if the metadata is missing or the credential is invalid, expired,
+///   rewound or replayed,
+/// - `PERMISSION_DENIED` if the credential is restricted to another UID or GID, or bound
+///   to another method,
+/// - `UNAVAILABLE` if munged cannot be reached,
+/// - `INTERNAL` for any other error.
+///
+/// The status messages are fixed and do not include the error of munged.
+///
+/// Decoding is asynchronous like in [`crate::MungeAuthLayer`], so it does not block the
+/// threads of the runtime, which is why this is a layer and not a tonic interceptor.
+///
+/// # Example
+///
+/// ```ignore
+/// Server::builder()
+///     .layer(MungeGrpcAuthLayer::new(Context::new()))
+///     .add_service(SchedulerServer::new(scheduler))
+///     .serve(addr)
+///     .await?;
+/// ```
+#[derive(Clone)]
+pub struct MungeGrpcAuthLayer {
+    backend: Arc<dyn MungeBackend>,
+    ctx: Arc<Context>,
+    timeout: Option<Duration>,
+}
+
+impl MungeGrpcAuthLayer {
+    /// Creates a layer decoding credentials with the given context.
+    pub fn new(ctx: impl Into<Arc<Context>>) -> Self {
+        MungeGrpcAuthLayer {
+            backend: Arc::new(Munged),
+            ctx: ctx.into(),
+            timeout: None,
+        }
+    }
+
+    /// Sets the backend decoding the credentials, [`Munged`] by default.
+    pub fn with_backend(mut self, backend: impl MungeBackend + 'static) -> Self {
+        self.backend = Arc::new(backend);
+        self
+    }
+
+    /// Sets the time after which decoding fails, rejecting the request with `UNAVAILABLE`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+impl fmt::Debug for MungeGrpcAuthLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MungeGrpcAuthLayer")
+            .field("ctx", &self.ctx)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> Layer<S> for MungeGrpcAuthLayer {
+    type Service = MungeGrpcAuth<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MungeGrpcAuth {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Service created by [`MungeGrpcAuthLayer`].
+#[derive(Debug, Clone)]
+pub struct MungeGrpcAuth<S> {
+    inner: S,
+    layer: MungeGrpcAuthLayer,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for MungeGrpcAuth<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    ReqBody: Send + 'static,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        // Call the instance that was polled ready, leaving a fresh clone in its place.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+
+        Box::pin(async move {
+            let header = HeaderName::from_static(METADATA_KEY);
+            let res = authenticate(
+                req.headers(),
+                &header,
+                &*layer.backend,
+                &layer.ctx,
+                layer.timeout,
+            )
+            .await;
+            let cred = match res {
+                Ok(cred) => cred,
+                Err(rejection) => return Ok(status_response(status(rejection))),
+            };
+            if cred.message != req.uri().path().as_bytes() {
+                return Ok(status_response(status(Rejection::Forbidden)));
+            }
+
+            insert_credential(req.extensions_mut(), cred);
+            inner.call(req).await
+        })
+    }
+}
+
+/// Maps a rejection to a gRPC status with a fixed message.
+fn status(rejection: Rejection) -> Status {
+    match rejection {
+        Rejection::Unauthenticated => {
+            Status::unauthenticated("Missing or invalid MUNGE credential.")
+        }
+        Rejection::Forbidden => {
+            Status::permission_denied("MUNGE credential is not valid for this method.")
+        }
+        Rejection::Unavailable => Status::unavailable("MUNGE authentication is unavailable."),
+        Rejection::Internal => Status::internal("MUNGE authentication failed."),
+    }
+}
+
+/// Builds a trailers-only gRPC response carrying the status in its headers.
+fn status_response<B: Default>(status: Status) -> Response<B> {
+    let mut res = Response::new(B::default());
+    res.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+    // The messages are fixed ASCII, so the headers are always valid.
+    let _ = status.add_header(res.headers_mut());
+    res
+}
+
+/// [`Layer`] attaching a freshly encoded MUNGE credential to each outgoing gRPC request.
+///
+/// The payload of the credential is the path of the called method,
+/// `/<package>.<Service>/<Method>`, which [`MungeGrpcAuthLayer`] checks on the server, so
+/// a captured credential cannot be used for another method. It is encoded with the
+/// shared [`Context`] by munged, or by the backend set with
+/// [`MungeGrpcClientLayer::with_backend`]. Encoding is asynchronous and its errors are
+/// returned as the error of the service.
+///
+/// # Example
+///
+/// ```ignore
+/// let channel = Endpoint::from_static("http://head:50051").connect().await?;
+/// let channel = ServiceBuilder::new()
+///     .layer(MungeGrpcClientLayer::new(Context::new()))
+///     .service(channel);
+/// let mut client = SchedulerClient::new(channel);
+/// ```
+#[derive(Clone)]
+pub struct MungeGrpcClientLayer {
+    backend: Arc<dyn MungeBackend>,
+    ctx: Arc<Context>,
+    timeout: Option<Duration>,
+}
+
+impl MungeGrpcClientLayer {
+    /// Creates a layer encoding credentials with the given context.
+    pub fn new(ctx: impl Into<Arc<Context>>) -> Self {
+        MungeGrpcClientLayer {
+            backend: Arc::new(Munged),
+            ctx: ctx.into(),
+            timeout: None,
+        }
+    }
+
+    /// Sets the backend encoding the credentials, [`Munged`] by default.
+    pub fn with_backend(mut self, backend: impl MungeBackend + 'static) -> Self {
+        self.backend = Arc::new(backend);
+        self
+    }
+
+    /// Sets the time after which encoding fails with [`crate::MungeError::Timeout`].
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+impl fmt::Debug for MungeGrpcClientLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MungeGrpcClientLayer")
+            .field("ctx", &self.ctx)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> Layer<S> for MungeGrpcClientLayer {
+    type Service = MungeGrpcClient<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MungeGrpcClient {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Service created by [`MungeGrpcClientLayer`].
+#[derive(Debug, Clone)]
+pub struct MungeGrpcClient<S> {
+    inner: S,
+    layer: MungeGrpcClientLayer,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for MungeGrpcClient<S>
+where
+    S: Service<Request<ReqBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+    ReqBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+
+        Box::pin(async move {
+            let path = req.uri().path().as_bytes();
+            let cred = layer
+                .backend
+                .encode_bytes_async(path, Some(&layer.ctx), layer.timeout)
+                .await?;
+            req.headers_mut().insert(
+                HeaderName::from_static(METADATA_KEY),
+                HeaderValue::try_from(cred)?,
+            );
+            inner.call(req).await.map_err(Into::into)
+        })
+    }
+}
+
+#[cfg(test)]
+mod grpc_tests {
+    use std::sync::Arc;
+
+    use http::{Request, Response};
+    use tonic::{Code, Status};
+    use tower_layer::Layer;
+    use tower_service::Service;
+
+    use crate::{
+        ctx::Context,
+        enums::{Error, MungeError},
+        grpc::{status, MungeGrpcAuthLayer, MungeGrpcClientLayer, METADATA_KEY},
+        middleware::{test_service::Echo, Rejection},
+        mock::MockBackend,
+    };
+
+    fn code(res: &Response<String>) -> Option<Code> {
+        Status::from_header_map(res.headers()).map(|status| status.code())
+    }
+
+    #[tokio::test]
+    async fn missing_credential() {
+        let mut service = MungeGrpcAuthLayer::new(Context::new()).layer(Echo);
+
+        let res = service.call(Request::new(())).await.unwrap();
+        assert_eq!(code(&res), Some(Code::Unauthenticated));
+        assert_eq!(res.headers()["content-type"], "application/grpc");
+    }
+
+    #[test]
+    fn statuses() {
+        let for_error = |e: MungeError| {
+            status(Rejection::from_error(&Error::MungeError(
+                e,
+                "/run/munge/munge.socket.2".to_string(),
+            )))
+        };
+
+        assert_eq!(
+            for_error(MungeError::CredExpired).code(),
+            Code::Unauthenticated
+        );
+        assert_eq!(
+            for_error(MungeError::CredReplayed).code(),
+            Code::Unauthenticated
+        );
+        assert_eq!(
+            for_error(MungeError::CredUnauthorized).code(),
+            Code::PermissionDenied
+        );
+        assert_eq!(for_error(MungeError::Socket).code(), Code::Unavailable);
+        assert_eq!(for_error(MungeError::Snafu).code(), Code::Internal);
+        assert!(!for_error(MungeError::Socket).message().contains("socket.2"));
+    }
+
+    #[tokio::test]
+    async fn method_binding() {
+        let mock = Arc::new(MockBackend::new().with_uid(1000));
+        let ctx = Arc::new(Context::new());
+        let mut client = MungeGrpcClientLayer::new(ctx.clone())
+            .with_backend(mock.clone())
+            .layer(Echo);
+        let mut server = MungeGrpcAuthLayer::new(ctx).with_backend(mock).layer(Echo);
+        let submit = "/scheduler.Scheduler/Submit";
+
+        let mut encode = || client.call(Request::builder().uri(submit).body(()).unwrap());
+        let cred = encode().await.unwrap().into_body();
+        let stolen = encode().await.unwrap().into_body();
+
+        let req = Request::builder()
+            .uri(submit)
+            .header(METADATA_KEY, cred)
+            .body(())
+            .unwrap();
+        let res = server.call(req).await.unwrap();
+        assert_eq!(code(&res), None);
+        assert_eq!(res.into_body(), "1000");
+
+        let req = Request::builder()
+            .uri("/scheduler.Scheduler/Cancel")
+            .header(METADATA_KEY, stolen)
+            .body(())
+            .unwrap();
+        let res = server.call(req).await.unwrap();
+        assert_eq!(code(&res), Some(Code::PermissionDenied));
+    }
+}
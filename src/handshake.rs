@@ -0,0 +1,460 @@
+use std::io::{self, Read, Write};
+
+use crate::{
+    backend::{MungeBackend, Munged},
+    credential::Credential,
+    ctx::Context,
+    enums::Error,
+};
+
+/// Tag at the start of every handshake payload, including the protocol version.
+const MAGIC: &[u8; 8] = b"MUNGEHS1";
+
+const NONCE_LEN: usize = 32;
+
+const PAYLOAD_LEN: usize = MAGIC.len() + 1 + 2 * NONCE_LEN;
+
+/// Largest frame accepted from the peer, far more than any credential of a handshake.
+const MAX_FRAME_LEN: u32 = 64 * 1024;
+
+type Nonce = [u8; NONCE_LEN];
+
+/// Side of the handshake a message was sent by, so that a message cannot be reflected
+/// back to its sender.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum Role {
+    Client = 1,
+    Server = 2,
+}
+
+/// Handshake message carried as credential payload.
+struct Message {
+    role: Role,
+    nonce: Nonce,
+    /// Nonce of the peer, all zeros in the first message of the client.
+    echo: Nonce,
+}
+
+impl Message {
+    fn pack(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(PAYLOAD_LEN);
+        payload.extend_from_slice(MAGIC);
+        payload.push(self.role as u8);
+        payload.extend_from_slice(&self.nonce);
+        payload.extend_from_slice(&self.echo);
+        payload
+    }
+
+    /// Checks that the payload was sent by the peer in the given role and echoes the
+    /// expected nonce, returning the nonce of the peer.
+    fn verify(payload: &[u8], role: Role, echo: &Nonce) -> Result<Nonce, Error> {
+        if payload.len() != PAYLOAD_LEN || !payload.starts_with(MAGIC) {
+            return Err(Error::Handshake("Peer sent an invalid handshake message."));
+        }
+        let (sender, rest) = payload[MAGIC.len()..].split_at(1);
+        let (nonce, echoed) = rest.split_at(NONCE_LEN);
+
+        if sender[0] != role as u8 {
+            return Err(Error::Handshake("Peer sent a message of the wrong role."));
+        }
+        if echoed != echo {
+            return Err(Error::Handshake("Peer did not echo the nonce."));
+        }
+        Ok(nonce.try_into().expect("nonce has NONCE_LEN bytes"))
+    }
+}
+
+pub(crate) fn random_nonce() -> Result<Nonce, Error> {
+    let mut nonce = [0; NONCE_LEN];
+    getrandom::getrandom(&mut nonce).map_err(io::Error::from)?;
+    Ok(nonce)
+}
+
+fn check_frame_len(len: u32) -> Result<usize, Error> {
+    if len > MAX_FRAME_LEN {
+        return Err(Error::Handshake("Peer sent an oversized handshake frame."));
+    }
+    Ok(len as usize)
+}
+
+/// Checks that the final credential of the client comes from the same user as its first
+/// one.
+fn same_peer(
+    first: &Credential<Vec<u8>>,
+    last: Credential<Vec<u8>>,
+) -> Result<Credential<()>, Error> {
+    if (first.uid, first.gid) != (last.uid, last.gid) {
+        return Err(Error::Handshake(
+            "Peer changed its identity during the handshake.",
+        ));
+    }
+    Ok(peer(last))
+}
+
+fn peer(cred: Credential<Vec<u8>>) -> Credential<()> {
+    Credential {
+        uid: cred.uid,
+        gid: cred.gid,
+        message: (),
+    }
+}
+
+fn write_frame<S: Write>(stream: &mut S, cred: &str) -> Result<(), Error> {
+    stream.write_all(&(cred.len() as u32).to_be_bytes())?;
+    stream.write_all(cred.as_bytes())?;
+    stream.flush()?;
+    Ok(())
+}
+
+fn read_frame<S: Read>(stream: &mut S) -> Result<String, Error> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len)?;
+    let mut cred = vec![0; check_frame_len(u32::from_be_bytes(len))?];
+    stream.read_exact(&mut cred)?;
+    Ok(String::from_utf8(cred)?)
+}
+
+/// Authenticates the server at the other end of the stream and to it, acting as the
+/// client of the handshake.
+///
+/// Both sides prove their identity with credentials carrying a fresh random nonce and
+/// the nonce of the peer, so that credentials captured from another handshake can
+/// neither be replayed nor reflected back. The handshake takes three messages:
+///
+/// 1. The client sends a credential with its nonce.
+/// 2. The server sends a credential with its nonce and the nonce of the client.
+/// 3. The client sends a credential with its nonce and the nonce of the server.
+///
+/// Each message is framed by its length as a 32-bit big-endian integer. Both sides need
+/// munged daemons sharing the same key.
+///
+/// # Arguments
+///
+/// * `stream` - The connection to the server.
+/// * `ctx` - An optional reference to a [`Context`]. If no context is provided, defaults will be used.
+///
+/// # Errors
+///
+/// Returns [`Error::Io`] if the stream fails, [`Error::Handshake`] if the server does
+/// not follow the protocol, or the errors of [`crate::encode_bytes`] and
+/// [`crate::decode_bytes`].
+///
+/// # Returns
+///
+/// The UID and GID of the server.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut stream = UnixStream::connect("/run/scheduler.sock")?;
+/// let server = handshake_client(&mut stream, None)?;
+/// assert_eq!(server.uid, 0);
+/// ```
+pub fn handshake_client<S: Read + Write>(
+    stream: &mut S,
+    ctx: Option<&Context>,
+) -> Result<Credential<()>, Error> {
+    handshake_client_with(&Munged, stream, ctx)
+}
+
+/// Authenticates the server at the other end of the stream with the given backend, see
+/// [`handshake_client`].
+///
+/// # Errors
+///
+/// Returns [`Error::Io`] if the stream fails, [`Error::Handshake`] if the server does
+/// not follow the protocol, or the errors of the backend.
+pub fn handshake_client_with<B: MungeBackend + ?Sized, S: Read + Write>(
+    backend: &B,
+    stream: &mut S,
+    ctx: Option<&Context>,
+) -> Result<Credential<()>, Error> {
+    let nonce = random_nonce()?;
+    let hello = Message {
+        role: Role::Client,
+        nonce,
+        echo: [0; NONCE_LEN],
+    };
+    write_frame(stream, &backend.encode_bytes(&hello.pack(), ctx)?)?;
+
+    let server = Credential::from(backend.decode_bytes(&read_frame(stream)?, ctx)?);
+    let server_nonce = Message::verify(&server.message, Role::Server, &nonce)?;
+
+    let finish = Message {
+        role: Role::Client,
+        nonce,
+        echo: server_nonce,
+    };
+    write_frame(stream, &backend.encode_bytes(&finish.pack(), ctx)?)?;
+
+    Ok(peer(server))
+}
+
+/// Authenticates the client at the other end of the stream and to it, acting as the
+/// server of the handshake.
+///
+/// See [`handshake_client`] for the protocol.
+///
+/// # Arguments
+///
+/// * `stream` - The connection to the client.
+/// * `ctx` - An optional reference to a [`Context`]. If no context is provided, defaults will be used.
+///
+/// # Errors
+///
+/// Returns [`Error::Io`] if the stream fails, [`Error::Handshake`] if the client does
+/// not follow the protocol, or the errors of [`crate::encode_bytes`] and
+/// [`crate::decode_bytes`].
+///
+/// # Returns
+///
+/// The UID and GID of the client.
+pub fn handshake_server<S: Read + Write>(
+    stream: &mut S,
+    ctx: Option<&Context>,
+) -> Result<Credential<()>, Error> {
+    handshake_server_with(&Munged, stream, ctx)
+}
+
+/// Authenticates the client at the other end of the stream with the given backend, see
+/// [`handshake_server`].
+///
+/// # Errors
+///
+/// Returns [`Error::Io`] if the stream fails, [`Error::Handshake`] if the client does
+/// not follow the protocol, or the errors of the backend.
+pub fn handshake_server_with<B: MungeBackend + ?Sized, S: Read + Write>(
+    backend: &B,
+    stream: &mut S,
+    ctx: Option<&Context>,
+) -> Result<Credential<()>, Error> {
+    let hello = Credential::from(backend.decode_bytes(&read_frame(stream)?, ctx)?);
+    let client_nonce = Message::verify(&hello.message, Role::Client, &[0; NONCE_LEN])?;
+
+    let nonce = random_nonce()?;
+    let reply = Message {
+        role: Role::Server,
+        nonce,
+        echo: client_nonce,
+    };
+    write_frame(stream, &backend.encode_bytes(&reply.pack(), ctx)?)?;
+
+    let finish = Credential::from(backend.decode_bytes(&read_frame(stream)?, ctx)?);
+    if Message::verify(&finish.message, Role::Client, &nonce)? != client_nonce {
+        return Err(Error::Handshake(
+            "Peer changed its nonce during the handshake.",
+        ));
+    }
+
+    same_peer(&hello, finish)
+}
+
+#[cfg(feature = "tokio")]
+mod asynchronous {
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+    use super::{check_frame_len, peer, random_nonce, same_peer, Message, Role, NONCE_LEN};
+    use crate::{
+        backend::{MungeBackend, Munged},
+        credential::Credential,
+        ctx::Context,
+        enums::Error,
+    };
+
+    async fn write_frame<S: AsyncWrite + Unpin>(stream: &mut S, cred: &str) -> Result<(), Error> {
+        stream.write_all(&(cred.len() as u32).to_be_bytes()).await?;
+        stream.write_all(cred.as_bytes()).await?;
+        stream.flush().await?;
+        Ok(())
+    }
+
+    async fn read_frame<S: AsyncRead + Unpin>(stream: &mut S) -> Result<String, Error> {
+        let len = check_frame_len(stream.read_u32().await?)?;
+        let mut cred = vec![0; len];
+        stream.read_exact(&mut cred).await?;
+        Ok(String::from_utf8(cred)?)
+    }
+
+    /// Asynchronous version of [`crate::handshake_client`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`crate::handshake_client`].
+    pub async fn handshake_client_async<S: AsyncRead + AsyncWrite + Unpin>(
+        stream: &mut S,
+        ctx: Option<&Context>,
+    ) -> Result<Credential<()>, Error> {
+        handshake_client_async_with(&Munged, stream, ctx).await
+    }
+
+    /// Asynchronous version of [`crate::handshake_client_with`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`crate::handshake_client_with`].
+    pub async fn handshake_client_async_with<
+        B: MungeBackend + ?Sized,
+        S: AsyncRead + AsyncWrite + Unpin,
+    >(
+        backend: &B,
+        stream: &mut S,
+        ctx: Option<&Context>,
+    ) -> Result<Credential<()>, Error> {
+        let nonce = random_nonce()?;
+        let hello = Message {
+            role: Role::Client,
+            nonce,
+            echo: [0; NONCE_LEN],
+        };
+        let cred = backend.encode_bytes_async(&hello.pack(), ctx, None).await?;
+        write_frame(stream, &cred).await?;
+
+        let cred = read_frame(stream).await?;
+        let server = Credential::from(backend.decode_bytes_async(&cred, ctx, None).await?);
+        let server_nonce = Message::verify(&server.message, Role::Server, &nonce)?;
+
+        let finish = Message {
+            role: Role::Client,
+            nonce,
+            echo: server_nonce,
+        };
+        let cred = backend
+            .encode_bytes_async(&finish.pack(), ctx, None)
+            .await?;
+        write_frame(stream, &cred).await?;
+
+        Ok(peer(server))
+    }
+
+    /// Asynchronous version of [`crate::handshake_server`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`crate::handshake_server`].
+    pub async fn handshake_server_async<S: AsyncRead + AsyncWrite + Unpin>(
+        stream: &mut S,
+        ctx: Option<&Context>,
+    ) -> Result<Credential<()>, Error> {
+        handshake_server_async_with(&Munged, stream, ctx).await
+    }
+
+    /// Asynchronous version of [`crate::handshake_server_with`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`crate::handshake_server_with`].
+    pub async fn handshake_server_async_with<
+        B: MungeBackend + ?Sized,
+        S: AsyncRead + AsyncWrite + Unpin,
+    >(
+        backend: &B,
+        stream: &mut S,
+        ctx: Option<&Context>,
+    ) -> Result<Credential<()>, Error> {
+        let cred = read_frame(stream).await?;
+        let hello = Credential::from(backend.decode_bytes_async(&cred, ctx, None).await?);
+        let client_nonce = Message::verify(&hello.message, Role::Client, &[0; NONCE_LEN])?;
+
+        let nonce = random_nonce()?;
+        let reply = Message {
+            role: Role::Server,
+            nonce,
+            echo: client_nonce,
+        };
+        let cred = backend.encode_bytes_async(&reply.pack(), ctx, None).await?;
+        write_frame(stream, &cred).await?;
+
+        let cred = read_frame(stream).await?;
+        let finish = Credential::from(backend.decode_bytes_async(&cred, ctx, None).await?);
+        if Message::verify(&finish.message, Role::Client, &nonce)? != client_nonce {
+            return Err(Error::Handshake(
+                "Peer changed its nonce during the handshake.",
+            ));
+        }
+
+        same_peer(&hello, finish)
+    }
+}
+
+#[cfg(feature = "tokio")]
+pub use asynchronous::{
+    handshake_client_async, handshake_client_async_with, handshake_server_async,
+    handshake_server_async_with,
+};
+
+#[cfg(test)]
+mod handshake_tests {
+    use std::{os::unix::net::UnixStream, thread};
+
+    use crate::{
+        enums::Error,
+        handshake::{handshake_client_with, handshake_server_with, Message, Role, NONCE_LEN},
+        mock::MockBackend,
+    };
+
+    #[test]
+    fn verify() {
+        let nonce = [7; NONCE_LEN];
+        let msg = Message {
+            role: Role::Server,
+            nonce: [1; NONCE_LEN],
+            echo: nonce,
+        }
+        .pack();
+
+        assert_eq!(
+            Message::verify(&msg, Role::Server, &nonce).unwrap(),
+            [1; NONCE_LEN]
+        );
+        // A server message reflected back to the server.
+        assert!(matches!(
+            Message::verify(&msg, Role::Client, &nonce),
+            Err(Error::Handshake(_))
+        ));
+        // A server message replayed into another handshake.
+        assert!(matches!(
+            Message::verify(&msg, Role::Server, &[8; NONCE_LEN]),
+            Err(Error::Handshake(_))
+        ));
+        assert!(matches!(
+            Message::verify(b"MUNGEHS1", Role::Server, &nonce),
+            Err(Error::Handshake(_))
+        ));
+    }
+
+    #[test]
+    fn handshake() {
+        let mock = MockBackend::new().with_uid(1000);
+        let (mut client, mut server) = UnixStream::pair().unwrap();
+
+        let (server_peer, client_peer) = thread::scope(|scope| {
+            let server = scope.spawn(|| handshake_server_with(&mock, &mut server, None));
+            let server_peer =
+                handshake_client_with(&mock, &mut client, None).expect("Client handshake failed");
+            (server_peer, server.join().unwrap())
+        });
+        let client_peer = client_peer.expect("Server handshake failed");
+
+        assert_eq!(server_peer.uid, 1000);
+        assert_eq!(client_peer.uid, 1000);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn handshake_async() {
+        use crate::handshake::{handshake_client_async_with, handshake_server_async_with};
+
+        let mock = &MockBackend::new().with_gid(100);
+        let (mut client, mut server) = tokio::io::duplex(1024);
+
+        // Each side owns its end, so that a failing side closes it instead of leaving the
+        // other one waiting.
+        let (client_res, server_res) = tokio::join!(
+            async move { handshake_client_async_with(mock, &mut client, None).await },
+            async move { handshake_server_async_with(mock, &mut server, None).await }
+        );
+        assert_eq!(client_res.expect("Client handshake failed").gid, 100);
+        assert_eq!(server_res.expect("Server handshake failed").gid, 100);
+    }
+}
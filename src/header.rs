@@ -0,0 +1,308 @@
+use std::str::FromStr;
+
+use crate::{
+    enums::{Error, MungeError},
+    MungeCipher, MungeMac, MungeZip,
+};
+
+const PREFIX: &str = "MUNGE:";
+const SUFFIX: &str = ":";
+
+/// Credential format version written by munged.
+const CRED_VERSION: u8 = 3;
+
+/// Unencrypted header of a credential.
+///
+/// munged stores the version, the cipher, MAC and compression types and the security
+/// realm in the clear, in front of the encrypted part of the credential. Parsing them
+/// does not need munged, so credentials can be inspected on hosts without a daemon or
+/// rejected cheaply before decoding, eg. when they are not encrypted.
+///
+/// Parsing succeeding does not mean the credential is valid, only munged can tell that.
+///
+/// # Example
+///
+/// ```ignore
+/// let header = CredentialHeader::parse(&encoded)?;
+/// if header.cipher == MungeCipher::None {
+///     return Err("unencrypted credentials are not accepted".into());
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CredentialHeader {
+    /// Version of the credential format.
+    pub version: u8,
+    /// Cipher type the credential is encrypted with.
+    pub cipher: MungeCipher,
+    /// MAC type the credential is signed with.
+    pub mac: MungeMac,
+    /// Compression type of the payload.
+    pub zip: MungeZip,
+    /// Security realm of the credential, empty if none.
+    pub realm: Vec<u8>,
+    /// Length of the binary credential, ie. after base64 decoding, in bytes.
+    pub length: usize,
+}
+
+impl CredentialHeader {
+    /// Parses the header of a credential string.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoded_msg` - The base64 encoded credential string, `MUNGE:...:`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`Error::MungeError`] with the error munged would report:
+    /// - [`MungeError::BadCred`] if the credential is not a base64 encoded credential or
+    ///   is too short.
+    /// - [`MungeError::BadVersion`] if the credential format version is not supported.
+    /// - [`MungeError::BadCipher`], [`MungeError::BadMac`] or [`MungeError::BadZip`] if
+    ///   the respective type is unknown.
+    pub fn parse(encoded_msg: &str) -> Result<Self, Error> {
+        let encoded = encoded_msg
+            .trim()
+            .strip_prefix(PREFIX)
+            .and_then(|s| s.strip_suffix(SUFFIX))
+            .ok_or_else(|| bad_cred("Credential lacks the MUNGE prefix or suffix."))?;
+        let cred = base64_decode(encoded.as_bytes())
+            .ok_or_else(|| bad_cred("Credential is not valid base64."))?;
+
+        let &[version, cipher, mac, zip, realm_len, ..] = cred.as_slice() else {
+            return Err(bad_cred("Credential header is truncated."));
+        };
+        if version != CRED_VERSION {
+            return Err(Error::MungeError(
+                MungeError::BadVersion,
+                format!("Invalid credential version {version}."),
+            ));
+        }
+
+        let cipher = MungeCipher::try_from(cipher as u32)
+            .ok()
+            .filter(|&cipher| cipher != MungeCipher::Default)
+            .ok_or_else(|| invalid(MungeError::BadCipher, "cipher", cipher))?;
+        let mac = MungeMac::try_from(mac as u32)
+            .ok()
+            .filter(|&mac| mac != MungeMac::Default)
+            .ok_or_else(|| invalid(MungeError::BadMac, "MAC", mac))?;
+        let zip = MungeZip::try_from(zip as u32)
+            .ok()
+            .filter(|&zip| zip != MungeZip::Default)
+            .ok_or_else(|| invalid(MungeError::BadZip, "compression", zip))?;
+
+        let realm_end = 5 + realm_len as usize;
+        if cred.len() < realm_end + iv_len(cipher) + mac_len(mac) {
+            return Err(bad_cred("Credential is truncated."));
+        }
+
+        Ok(CredentialHeader {
+            version,
+            cipher,
+            mac,
+            zip,
+            realm: cred[5..realm_end].to_vec(),
+            length: cred.len(),
+        })
+    }
+}
+
+impl FromStr for CredentialHeader {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CredentialHeader::parse(s)
+    }
+}
+
+fn bad_cred(msg: &str) -> Error {
+    Error::MungeError(MungeError::BadCred, msg.to_string())
+}
+
+fn invalid(err: MungeError, kind: &str, value: u8) -> Error {
+    Error::MungeError(err, format!("Invalid {kind} type {value}."))
+}
+
+/// Length of the initialization vector following the realm.
+fn iv_len(cipher: MungeCipher) -> usize {
+    match cipher {
+        MungeCipher::None | MungeCipher::Default => 0,
+        MungeCipher::Blowfish | MungeCipher::Cast5 => 8,
+        MungeCipher::Aes128 | MungeCipher::Aes256 => 16,
+    }
+}
+
+/// Length of the MAC following the initialization vector.
+fn mac_len(mac: MungeMac) -> usize {
+    match mac {
+        MungeMac::None | MungeMac::Default => 0,
+        MungeMac::MD5 => 16,
+        MungeMac::SHA1 | MungeMac::RIPEMD160 => 20,
+        MungeMac::SHA256 => 32,
+        MungeMac::SHA512 => 64,
+    }
+}
+
+/// Decodes padded standard base64 as written by munged.
+fn base64_decode(input: &[u8]) -> Option<Vec<u8>> {
+    fn value(c: u8) -> Option<u32> {
+        Some(match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        } as u32)
+    }
+
+    // `is_multiple_of` needs Rust 1.87, which nothing else in the crate requires.
+    #[allow(clippy::manual_is_multiple_of)]
+    if input.len() % 4 != 0 {
+        return None;
+    }
+
+    let mut out = Vec::with_capacity(input.len() / 4 * 3);
+    for (i, chunk) in input.chunks(4).enumerate() {
+        let last = i == input.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+
+        let mut bits = 0;
+        for &c in &chunk[..4 - padding] {
+            bits = bits << 6 | value(c)?;
+        }
+        bits <<= 6 * padding;
+        out.extend_from_slice(&bits.to_be_bytes()[1..4 - padding]);
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod header_tests {
+    use crate::{
+        enums::{Error, MungeError},
+        header::{base64_decode, CredentialHeader},
+        MungeCipher, MungeMac, MungeZip,
+    };
+
+    fn base64_encode(input: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut out = String::new();
+        for chunk in input.chunks(3) {
+            let mut bytes = [0; 3];
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    out.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+                } else {
+                    out.push('=');
+                }
+            }
+        }
+        out
+    }
+
+    fn credential(header: &[u8], body_len: usize) -> String {
+        let mut cred = header.to_vec();
+        cred.resize(header.len() + body_len, 0xa5);
+        format!("MUNGE:{}:", base64_encode(&cred))
+    }
+
+    fn munge_error(res: Result<CredentialHeader, Error>) -> Option<MungeError> {
+        res.unwrap_err().munge_error().copied()
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!(base64_decode(b"TWFu").unwrap(), b"Man");
+        assert_eq!(base64_decode(b"TWE=").unwrap(), b"Ma");
+        assert_eq!(base64_decode(b"TQ==").unwrap(), b"M");
+        assert_eq!(base64_decode(b"").unwrap(), b"");
+        assert!(base64_decode(b"TQ=").is_none());
+        assert!(base64_decode(b"TQ==TWFu").is_none());
+        assert!(base64_decode(b"T!Fu").is_none());
+    }
+
+    #[test]
+    fn parse() {
+        let header = [
+            3,
+            MungeCipher::Aes256 as u8,
+            MungeMac::SHA256 as u8,
+            MungeZip::Zlib as u8,
+            4,
+            b'c',
+            b'o',
+            b'r',
+            b'p',
+        ];
+        let cred = credential(&header, 16 + 32 + 40);
+
+        let parsed: CredentialHeader = cred.parse().unwrap();
+        assert_eq!(parsed.version, 3);
+        assert_eq!(parsed.cipher, MungeCipher::Aes256);
+        assert_eq!(parsed.mac, MungeMac::SHA256);
+        assert_eq!(parsed.zip, MungeZip::Zlib);
+        assert_eq!(parsed.realm, b"corp");
+        assert_eq!(parsed.length, header.len() + 16 + 32 + 40);
+
+        let header = [3, MungeCipher::None as u8, MungeMac::SHA1 as u8, 0, 0];
+        let parsed = CredentialHeader::parse(&credential(&header, 40)).unwrap();
+        assert_eq!(parsed.cipher, MungeCipher::None);
+        assert!(parsed.realm.is_empty());
+    }
+
+    #[test]
+    fn parse_invalid() {
+        let cipher = MungeCipher::Aes128 as u8;
+        let mac = MungeMac::SHA256 as u8;
+
+        assert_eq!(
+            munge_error(CredentialHeader::parse("MUNGE:AwQFAAA")),
+            Some(MungeError::BadCred)
+        );
+        assert_eq!(
+            munge_error(CredentialHeader::parse(&credential(&[3, cipher], 0))),
+            Some(MungeError::BadCred)
+        );
+        assert_eq!(
+            munge_error(CredentialHeader::parse(&credential(
+                &[3, cipher, mac, 0, 0],
+                16
+            ))),
+            Some(MungeError::BadCred)
+        );
+        assert_eq!(
+            munge_error(CredentialHeader::parse(&credential(
+                &[2, cipher, mac, 0, 0],
+                48
+            ))),
+            Some(MungeError::BadVersion)
+        );
+        assert_eq!(
+            munge_error(CredentialHeader::parse(&credential(
+                &[3, 42, mac, 0, 0],
+                48
+            ))),
+            Some(MungeError::BadCipher)
+        );
+        assert_eq!(
+            munge_error(CredentialHeader::parse(&credential(
+                &[3, cipher, MungeMac::Default as u8, 0, 0],
+                48
+            ))),
+            Some(MungeError::BadMac)
+        );
+        assert_eq!(
+            munge_error(CredentialHeader::parse(&credential(
+                &[3, cipher, mac, 9, 0],
+                48
+            ))),
+            Some(MungeError::BadZip)
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    fmt,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    backend::{MungeBackend, Munged},
+    ctx::Context,
+    enums::{Error, MungeError},
+    MungeCipher, MungeMac, MungeZip,
+};
+
+/// Stage of a [`health_check`] that failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthStage {
+    /// The context could not be created or its socket could not be read.
+    Context,
+    /// munged could not encode the probe credential.
+    Encode,
+    /// munged could not decode the probe credential.
+    Decode,
+    /// The decoded probe credential did not match the encoded one.
+    Verify,
+}
+
+impl fmt::Display for HealthStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HealthStage::Context => "context",
+            HealthStage::Encode => "encode",
+            HealthStage::Decode => "decode",
+            HealthStage::Verify => "verify",
+        })
+    }
+}
+
+/// Failure of a [`health_check`].
+#[derive(Debug)]
+pub struct HealthFailure {
+    /// Stage at which the check failed.
+    pub stage: HealthStage,
+    /// Error reported at that stage.
+    pub error: Error,
+}
+
+/// Report of a [`health_check`].
+///
+/// Fields describing the probe credential are only present if it was decoded.
+#[derive(Debug)]
+pub struct HealthReport {
+    /// Path of the munged socket that was probed.
+    pub socket: Option<PathBuf>,
+    /// Time taken by the encode/decode round-trip, up to the failure if any.
+    pub latency: Duration,
+    /// Cipher type munged used for the probe credential.
+    pub cipher: Option<MungeCipher>,
+    /// MAC type munged used for the probe credential.
+    pub mac: Option<MungeMac>,
+    /// Compression type munged used for the probe credential.
+    pub zip: Option<MungeZip>,
+    /// Time-to-live munged gave the probe credential in seconds.
+    pub ttl: Option<i32>,
+    /// Decode time minus encode time as reported by munged, at a resolution of one
+    /// second.
+    pub clock_skew: Option<chrono::Duration>,
+    /// The failure, or `None` if munged is healthy.
+    pub failure: Option<HealthFailure>,
+}
+
+impl HealthReport {
+    /// Returns `true` if the round-trip succeeded.
+    pub fn is_healthy(&self) -> bool {
+        self.failure.is_none()
+    }
+
+    fn failed(mut self, stage: HealthStage, error: Error) -> Self {
+        self.failure = Some(HealthFailure { stage, error });
+        self
+    }
+}
+
+/// Checks whether munged is reachable and working.
+///
+/// An empty payload is encoded and decoded again with the options of the given context,
+/// and the outcome is reported instead of returned as an error. The context is cloned, so
+/// the metadata of its last decoded credential is left untouched.
+///
+/// # Arguments
+///
+/// * `ctx` - An optional reference to a [`Context`]. If no context is provided, defaults will be used.
+///
+/// # Example
+///
+/// ```ignore
+/// let report = health_check(None);
+/// match &report.failure {
+///     None => println!("munged is healthy ({:?})", report.latency),
+///     Some(failure) => eprintln!("munged failed to {}: {}", failure.stage, failure.error),
+/// }
+/// ```
+pub fn health_check(ctx: Option<&Context>) -> HealthReport {
+    health_check_with(&Munged, ctx)
+}
+
+/// Checks whether the given backend is reachable and working, see [`health_check`].
+///
+/// The socket of the context is reported as is, as the backend may not use it.
+pub fn health_check_with<B: MungeBackend + ?Sized>(
+    backend: &B,
+    ctx: Option<&Context>,
+) -> HealthReport {
+    let mut report = HealthReport {
+        socket: None,
+        latency: Duration::ZERO,
+        cipher: None,
+        mac: None,
+        zip: None,
+        ttl: None,
+        clock_skew: None,
+        failure: None,
+    };
+
+    let ctx = match ctx.cloned().map_or_else(Context::try_new, Ok) {
+        Ok(ctx) => ctx,
+        Err(error) => return report.failed(HealthStage::Context, error),
+    };
+    match ctx.socket() {
+        Ok(socket) => report.socket = Some(socket),
+        Err(error) => return report.failed(HealthStage::Context, error),
+    }
+
+    let started = Instant::now();
+    let cred = backend.encode_bytes(&[], Some(&ctx));
+    report.latency = started.elapsed();
+    let cred = match cred {
+        Ok(cred) => cred,
+        Err(error) => return report.failed(HealthStage::Encode, error),
+    };
+
+    let decoded = backend.decode_bytes(&cred, Some(&ctx));
+    report.latency = started.elapsed();
+    let decoded = match decoded {
+        Ok(decoded) => decoded,
+        Err(error) => return report.failed(HealthStage::Decode, error),
+    };
+
+    report.cipher = Some(decoded.cipher);
+    report.mac = Some(decoded.mac);
+    report.zip = Some(decoded.zip);
+    report.ttl = Some(decoded.ttl);
+    report.clock_skew = Some(decoded.decode_time - decoded.encode_time);
+
+    if !decoded.message.is_empty() {
+        return report.failed(
+            HealthStage::Verify,
+            Error::MungeError(
+                MungeError::Snafu,
+                "Decoded probe credential has a non-empty payload.".to_string(),
+            ),
+        );
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod health_tests {
+    use crate::{
+        ctx::Context,
+        enums::MungeError,
+        health::{health_check_with, HealthStage},
+        mock::MockBackend,
+        MungeCipher,
+    };
+
+    #[test]
+    fn healthy() {
+        let mock = MockBackend::new();
+        mock.set_time(chrono::Utc::now());
+        let ctx = Context::new();
+        let report = health_check_with(&mock, Some(&ctx));
+
+        assert!(report.is_healthy(), "{:?}", report.failure);
+        assert_eq!(report.socket, Some(ctx.socket().unwrap()));
+        assert_eq!(report.cipher, Some(MungeCipher::Aes128));
+        assert_eq!(report.clock_skew, Some(chrono::Duration::zero()));
+    }
+
+    #[test]
+    fn failed_stage() {
+        let mock = MockBackend::new();
+        mock.fail_next(MungeError::Socket);
+        let report = health_check_with(&mock, None);
+
+        let failure = report.failure.expect("Health check did not fail");
+        assert_eq!(failure.stage, HealthStage::Encode);
+        assert_eq!(failure.error.munge_error(), Some(&MungeError::Socket));
+    }
+}
@@ -9,12 +9,77 @@
 )]
 mod ffi;
 
+#[cfg(feature = "tokio")]
+mod asynchronous;
+mod backend;
+mod challenge;
+mod config;
 mod credential;
 mod ctx;
 mod enums;
+mod envelope;
+mod failover;
+#[cfg(feature = "tonic")]
+mod grpc;
+mod handshake;
+mod header;
+mod health;
+#[cfg(feature = "tower")]
+mod middleware;
+#[cfg(any(test, feature = "mock"))]
+mod mock;
 mod munge;
+#[cfg(feature = "nss")]
+mod nss;
+mod policy;
+#[cfg(feature = "replay-cache")]
+mod replay;
+mod retry;
+mod sys;
+#[cfg(feature = "serde")]
+mod value;
 
-pub use credential::Credential;
-pub use ctx::Context;
+#[cfg(feature = "tokio")]
+pub use asynchronous::{decode_async, decode_bytes_async, encode_async, encode_bytes_async};
+#[cfg(feature = "tokio")]
+pub use backend::BackendFuture;
+pub use backend::{MungeBackend, Munged};
+pub use challenge::{respond_to_challenge, respond_to_challenge_with, Challenge};
+pub use config::ContextConfig;
+pub use credential::{Credential, DecodedCredential, RawCredential};
+pub use ctx::{Context, ContextBuilder};
 pub use enums::{Error, MungeCipher, MungeError, MungeMac, MungeZip};
-pub use munge::{decode, encode};
+pub use envelope::{
+    decode_envelope, decode_envelope_with, encode_envelope, encode_envelope_with, verify_envelope,
+    verify_envelope_with, Envelope,
+};
+pub use failover::{FailoverContext, Served};
+#[cfg(feature = "tonic")]
+pub use grpc::{
+    MungeGrpcAuth, MungeGrpcAuthLayer, MungeGrpcClient, MungeGrpcClientLayer, METADATA_KEY,
+};
+pub use handshake::{
+    handshake_client, handshake_client_with, handshake_server, handshake_server_with,
+};
+#[cfg(feature = "tokio")]
+pub use handshake::{
+    handshake_client_async, handshake_client_async_with, handshake_server_async,
+    handshake_server_async_with,
+};
+pub use header::CredentialHeader;
+pub use health::{health_check, health_check_with, HealthFailure, HealthReport, HealthStage};
+#[cfg(feature = "tower")]
+pub use middleware::{
+    BoxError, MungeAuth, MungeAuthLayer, MungeClient, MungeClientLayer, DEFAULT_HEADER,
+};
+#[cfg(feature = "mock")]
+pub use mock::MockBackend;
+pub use munge::{decode, decode_bytes, decode_full, decode_full_bytes, encode, encode_bytes};
+#[cfg(feature = "nss")]
+pub use nss::{NssCache, UserInfo};
+pub use policy::{decode_bytes_with_policy, decode_with_policy, Policy, PolicyViolation};
+#[cfg(feature = "replay-cache")]
+pub use replay::ReplayCache;
+pub use retry::RetryPolicy;
+#[cfg(feature = "serde")]
+pub use value::{decode_value, encode_value, PayloadFormat};
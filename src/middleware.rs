@@ -0,0 +1,486 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context as TaskContext, Poll},
+    time::Duration,
+};
+
+use http::{header::HeaderName, Extensions, HeaderMap, HeaderValue, Request, Response, StatusCode};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{
+    backend::{MungeBackend, Munged},
+    credential::{Credential, RawCredential},
+    ctx::Context,
+    enums::{Error, MungeError},
+};
+
+/// Header carrying the credential unless configured otherwise.
+pub const DEFAULT_HEADER: &str = "x-munge-credential";
+
+/// Error type of the services of [`MungeClientLayer`].
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+pub(crate) type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// [`Layer`] authenticating HTTP requests by the MUNGE credential in a header.
+///
+/// The credential is decoded with the shared [`Context`] by munged, or by the backend set
+/// with [`MungeAuthLayer::with_backend`], and, on success, inserted into the request
+/// extensions before the request is passed on. It is always there as a
+/// [`RawCredential`], ie. `Credential<Vec<u8>>`, and also as a [`Credential`] if its
+/// payload is valid UTF-8, which includes the empty payload of [`MungeClientLayer`].
+/// Otherwise the request is answered right away with an empty body and:
+/// - `401 Unauthorized` if the header is missing or the credential is invalid, expired,
+///   rewound or replayed,
+/// - `403 Forbidden` if the credential is restricted to another UID or GID,
+/// - `503 Service Unavailable` if munged cannot be reached,
+/// - `500 Internal Server Error` for any other error.
+///
+/// # Example
+///
+/// ```ignore
+/// let app = Router::new()
+///     .route("/jobs", post(submit))
+///     .layer(MungeAuthLayer::new(Context::new()).with_timeout(Duration::from_secs(2)));
+///
+/// async fn submit(Extension(cred): Extension<Credential>) -> String {
+///     format!("Hello UID {}", cred.uid)
+/// }
+/// ```
+#[derive(Clone)]
+pub struct MungeAuthLayer {
+    backend: Arc<dyn MungeBackend>,
+    ctx: Arc<Context>,
+    header: HeaderName,
+    timeout: Option<Duration>,
+}
+
+impl MungeAuthLayer {
+    /// Creates a layer decoding credentials with the given context.
+    pub fn new(ctx: impl Into<Arc<Context>>) -> Self {
+        MungeAuthLayer {
+            backend: Arc::new(Munged),
+            ctx: ctx.into(),
+            header: HeaderName::from_static(DEFAULT_HEADER),
+            timeout: None,
+        }
+    }
+
+    /// Sets the backend decoding the credentials, [`Munged`] by default.
+    pub fn with_backend(mut self, backend: impl MungeBackend + 'static) -> Self {
+        self.backend = Arc::new(backend);
+        self
+    }
+
+    /// Sets the header the credential is read from, [`DEFAULT_HEADER`] by default.
+    pub fn with_header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Sets the time after which decoding fails with [`MungeError::Timeout`].
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+impl fmt::Debug for MungeAuthLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MungeAuthLayer")
+            .field("ctx", &self.ctx)
+            .field("header", &self.header)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> Layer<S> for MungeAuthLayer {
+    type Service = MungeAuth<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MungeAuth {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Service created by [`MungeAuthLayer`].
+#[derive(Debug, Clone)]
+pub struct MungeAuth<S> {
+    inner: S,
+    layer: MungeAuthLayer,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for MungeAuth<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    ReqBody: Send + 'static,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        // Call the instance that was polled ready, leaving a fresh clone in its place.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+
+        Box::pin(async move {
+            let res = authenticate(
+                req.headers(),
+                &layer.header,
+                &*layer.backend,
+                &layer.ctx,
+                layer.timeout,
+            )
+            .await;
+            match res {
+                Ok(cred) => {
+                    insert_credential(req.extensions_mut(), cred);
+                    inner.call(req).await
+                }
+                Err(rejection) => Ok(rejection.into_response()),
+            }
+        })
+    }
+}
+
+/// Reason a request is rejected, shared by the HTTP and gRPC layers so both transports
+/// answer a failed decode the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Rejection {
+    /// The credential is missing, invalid, expired, rewound or replayed, or could not be
+    /// read at all.
+    Unauthenticated,
+    /// The credential is restricted to another UID or GID, or bound to another method.
+    Forbidden,
+    /// munged cannot be reached.
+    Unavailable,
+    /// Any other error.
+    Internal,
+}
+
+impl Rejection {
+    /// Classifies a decoding error.
+    ///
+    /// Errors that do not come from munged, eg. a header that is not a valid C string,
+    /// are caused by the request and reported as [`Rejection::Unauthenticated`].
+    pub(crate) fn from_error(err: &Error) -> Self {
+        match err.munge_error() {
+            Some(MungeError::CredUnauthorized) => Rejection::Forbidden,
+            Some(e) if e.is_transport_error() => Rejection::Unavailable,
+            Some(
+                MungeError::BadCred
+                | MungeError::BadVersion
+                | MungeError::BadCipher
+                | MungeError::BadMac
+                | MungeError::BadZip
+                | MungeError::BadRealm
+                | MungeError::CredInvalid
+                | MungeError::CredExpired
+                | MungeError::CredRewound
+                | MungeError::CredReplayed,
+            )
+            | None => Rejection::Unauthenticated,
+            Some(_) => Rejection::Internal,
+        }
+    }
+
+    fn status(self) -> StatusCode {
+        match self {
+            Rejection::Unauthenticated => StatusCode::UNAUTHORIZED,
+            Rejection::Forbidden => StatusCode::FORBIDDEN,
+            Rejection::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            Rejection::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn into_response<B: Default>(self) -> Response<B> {
+        let mut res = Response::new(B::default());
+        *res.status_mut() = self.status();
+        res
+    }
+}
+
+/// Decodes the credential in the given header of a request.
+pub(crate) async fn authenticate(
+    headers: &HeaderMap,
+    header: &HeaderName,
+    backend: &dyn MungeBackend,
+    ctx: &Context,
+    timeout: Option<Duration>,
+) -> Result<RawCredential, Rejection> {
+    let cred = headers
+        .get(header)
+        .and_then(|value| value.to_str().ok())
+        .ok_or(Rejection::Unauthenticated)?;
+
+    match backend.decode_bytes_async(cred, Some(ctx), timeout).await {
+        Ok(cred) => Ok(Credential::from(cred)),
+        Err(e) => Err(Rejection::from_error(&e)),
+    }
+}
+
+/// Inserts the decoded credential into the request extensions, also as a [`Credential`]
+/// if its payload is valid UTF-8.
+pub(crate) fn insert_credential(extensions: &mut Extensions, cred: RawCredential) {
+    if let Ok(message) = std::str::from_utf8(&cred.message) {
+        extensions.insert(Credential {
+            uid: cred.uid,
+            gid: cred.gid,
+            message: message.to_string(),
+        });
+    }
+    extensions.insert(cred);
+}
+
+/// [`Layer`] attaching a freshly encoded MUNGE credential to each outgoing HTTP request.
+///
+/// The credential is encoded with the shared [`Context`] by munged, or by the backend set
+/// with [`MungeClientLayer::with_backend`], so its TTL and UID/GID restrictions apply.
+/// It carries the configured payload, empty by default. Encoding errors are returned as
+/// the error of the service.
+///
+/// # Example
+///
+/// ```ignore
+/// let client = ServiceBuilder::new()
+///     .layer(MungeClientLayer::new(Context::new()).with_payload("scheduler"))
+///     .service(hyper_client);
+/// ```
+#[derive(Clone)]
+pub struct MungeClientLayer {
+    backend: Arc<dyn MungeBackend>,
+    ctx: Arc<Context>,
+    header: HeaderName,
+    payload: Arc<[u8]>,
+    timeout: Option<Duration>,
+}
+
+impl MungeClientLayer {
+    /// Creates a layer encoding credentials with the given context.
+    pub fn new(ctx: impl Into<Arc<Context>>) -> Self {
+        MungeClientLayer {
+            backend: Arc::new(Munged),
+            ctx: ctx.into(),
+            header: HeaderName::from_static(DEFAULT_HEADER),
+            payload: Arc::new([]),
+            timeout: None,
+        }
+    }
+
+    /// Sets the backend encoding the credentials, [`Munged`] by default.
+    pub fn with_backend(mut self, backend: impl MungeBackend + 'static) -> Self {
+        self.backend = Arc::new(backend);
+        self
+    }
+
+    /// Sets the header the credential is written to, [`DEFAULT_HEADER`] by default.
+    pub fn with_header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Sets the payload of the credentials.
+    pub fn with_payload(mut self, payload: impl AsRef<[u8]>) -> Self {
+        self.payload = payload.as_ref().into();
+        self
+    }
+
+    /// Sets the time after which encoding fails with [`MungeError::Timeout`].
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+impl fmt::Debug for MungeClientLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MungeClientLayer")
+            .field("ctx", &self.ctx)
+            .field("header", &self.header)
+            .field("payload", &self.payload)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> Layer<S> for MungeClientLayer {
+    type Service = MungeClient<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MungeClient {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Service created by [`MungeClientLayer`].
+#[derive(Debug, Clone)]
+pub struct MungeClient<S> {
+    inner: S,
+    layer: MungeClientLayer,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for MungeClient<S>
+where
+    S: Service<Request<ReqBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+    ReqBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+
+        Box::pin(async move {
+            let cred = layer
+                .backend
+                .encode_bytes_async(&layer.payload, Some(&layer.ctx), layer.timeout)
+                .await?;
+            req.headers_mut()
+                .insert(layer.header, HeaderValue::try_from(cred)?);
+            inner.call(req).await.map_err(Into::into)
+        })
+    }
+}
+
+/// Inner service shared by the tests of the tower and tonic layers.
+#[cfg(test)]
+pub(crate) mod test_service {
+    use std::{
+        convert::Infallible,
+        future::{ready, Ready},
+        task::{Context as TaskContext, Poll},
+    };
+
+    use http::{Request, Response};
+    use tower_service::Service;
+
+    use crate::{
+        credential::{Credential, RawCredential},
+        middleware::DEFAULT_HEADER,
+    };
+
+    /// Answers with the UID of the authenticated credential, or echoes the credential
+    /// header when there is none.
+    #[derive(Clone)]
+    pub(crate) struct Echo;
+
+    impl Service<Request<()>> for Echo {
+        type Response = Response<String>;
+        type Error = Infallible;
+        type Future = Ready<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, _cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: Request<()>) -> Self::Future {
+            let body = match req.extensions().get::<RawCredential>() {
+                Some(raw) => {
+                    // The payloads of the tests are valid UTF-8, so the credential is
+                    // also there as a `Credential`.
+                    let cred = req.extensions().get::<Credential>().unwrap();
+                    assert_eq!(cred.message.as_bytes(), raw.message);
+                    raw.uid.to_string()
+                }
+                None => req.headers()[DEFAULT_HEADER].to_str().unwrap().to_string(),
+            };
+            ready(Ok(Response::new(body)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod middleware_tests {
+    use std::sync::Arc;
+
+    use http::{Request, StatusCode};
+    use tower_layer::Layer;
+    use tower_service::Service;
+
+    use crate::{
+        ctx::Context,
+        enums::{Error, MungeError},
+        middleware::{
+            test_service::Echo, MungeAuthLayer, MungeClientLayer, Rejection, DEFAULT_HEADER,
+        },
+        mock::MockBackend,
+    };
+
+    #[tokio::test]
+    async fn missing_header() {
+        let mut service = MungeAuthLayer::new(Context::new()).layer(Echo);
+
+        let res = service.call(Request::new(())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn statuses() {
+        let status =
+            |e: MungeError| Rejection::from_error(&Error::MungeError(e, String::new())).status();
+
+        assert_eq!(status(MungeError::CredExpired), StatusCode::UNAUTHORIZED);
+        assert_eq!(status(MungeError::CredUnauthorized), StatusCode::FORBIDDEN);
+        assert_eq!(status(MungeError::Socket), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            status(MungeError::NoMemory),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        assert_eq!(
+            Rejection::from_error(&Error::InnerNull(std::ffi::CString::new("\0").unwrap_err())),
+            Rejection::Unauthenticated
+        );
+    }
+
+    #[tokio::test]
+    async fn client_and_server() {
+        let mock = Arc::new(MockBackend::new().with_uid(1000));
+        let ctx = Arc::new(Context::new());
+        let mut client = MungeClientLayer::new(ctx.clone())
+            .with_backend(mock.clone())
+            .layer(Echo);
+        let mut server = MungeAuthLayer::new(ctx).with_backend(mock).layer(Echo);
+
+        let cred = client.call(Request::new(())).await.unwrap().into_body();
+        assert!(cred.starts_with("MUNGE:"));
+
+        let request = || {
+            Request::builder()
+                .header(DEFAULT_HEADER, &cred)
+                .body(())
+                .unwrap()
+        };
+        let res = server.call(request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.into_body(), "1000");
+
+        let replayed = server.call(request()).await.unwrap();
+        assert_eq!(replayed.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,362 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    net::Ipv4Addr,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::{
+    backend::MungeBackend,
+    credential::{Credential, DecodedCredential},
+    ctx::Context,
+    enums::{Error, MungeError},
+    MungeCipher, MungeMac, MungeZip,
+};
+
+/// Wildcard value of the UID/GID restrictions, matching `MUNGE_UID_ANY`/`MUNGE_GID_ANY`.
+const ANY_ID: u32 = u32::MAX;
+
+/// In-process [`MungeBackend`] minting and validating credentials without munged.
+///
+/// Credentials are only valid for the `MockBackend` that minted them. The mock follows
+/// the rules of munged: credentials expire after their TTL, are rewound when the clock
+/// is before their encode time, are replayed when decoded more than once and are
+/// unauthorized when the decoding UID/GID does not match their restrictions.
+///
+/// The clock can be set and advanced, and failures can be injected for the next calls.
+///
+/// # Example
+///
+/// ```ignore
+/// let mock = MockBackend::new().with_uid(1000).with_gid(100);
+/// let cred = mock.encode("payload", None)?;
+/// mock.advance(chrono::Duration::seconds(600));
+/// assert!(matches!(mock.decode(&cred, None), Err(Error::CredentialRejected(MungeError::CredExpired, ..))));
+/// ```
+#[derive(Debug)]
+pub struct MockBackend {
+    uid: u32,
+    gid: u32,
+    addr4: Ipv4Addr,
+    default_ttl: u32,
+    max_ttl: u32,
+    state: Mutex<MockState>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    now: Option<DateTime<Utc>>,
+    next_id: u64,
+    minted: HashMap<String, Minted>,
+    decoded: HashSet<String>,
+    failures: VecDeque<MungeError>,
+}
+
+#[derive(Debug, Clone)]
+struct Minted {
+    cred: Credential<Vec<u8>>,
+    encode_time: DateTime<Utc>,
+    ttl: u32,
+    cipher: MungeCipher,
+    mac: MungeMac,
+    zip: MungeZip,
+    uid_restriction: u32,
+    gid_restriction: u32,
+}
+
+impl MockBackend {
+    /// Creates a mock acting as the current process, using the system clock and the
+    /// default TTLs of munged (300 seconds, at most 3600 seconds).
+    pub fn new() -> Self {
+        MockBackend {
+            uid: unsafe { libc::getuid() },
+            gid: unsafe { libc::getgid() },
+            addr4: Ipv4Addr::LOCALHOST,
+            default_ttl: 300,
+            max_ttl: 3600,
+            state: Mutex::new(MockState::default()),
+        }
+    }
+
+    /// Sets the UID stamped into minted credentials and used to check UID restrictions.
+    pub fn with_uid(mut self, uid: u32) -> Self {
+        self.uid = uid;
+        self
+    }
+
+    /// Sets the GID stamped into minted credentials and used to check GID restrictions.
+    pub fn with_gid(mut self, gid: u32) -> Self {
+        self.gid = gid;
+        self
+    }
+
+    /// Sets the origin address reported for decoded credentials.
+    pub fn with_addr4(mut self, addr4: Ipv4Addr) -> Self {
+        self.addr4 = addr4;
+        self
+    }
+
+    /// Sets the TTL used when the context asks for the default TTL.
+    pub fn with_default_ttl(mut self, ttl: u32) -> Self {
+        self.default_ttl = ttl;
+        self
+    }
+
+    /// Sets the largest TTL a credential can get.
+    pub fn with_max_ttl(mut self, ttl: u32) -> Self {
+        self.max_ttl = ttl;
+        self
+    }
+
+    /// Freezes the clock of the mock at the given time.
+    pub fn set_time(&self, now: DateTime<Utc>) {
+        self.lock().now = Some(now);
+    }
+
+    /// Moves the clock of the mock forward, or backward for a negative duration.
+    ///
+    /// The clock is frozen at the current system time first if it was not set yet.
+    pub fn advance(&self, by: Duration) {
+        let mut state = self.lock();
+        state.now = Some(state.now.unwrap_or_else(Utc::now) + by);
+    }
+
+    /// Makes the next encode or decode call fail with the given error.
+    ///
+    /// Failures are queued, each one is consumed by a single call. For
+    /// [`MungeError::CredExpired`], [`MungeError::CredRewound`] and
+    /// [`MungeError::CredReplayed`] a decode of a known credential reports the partial
+    /// credential like munged does.
+    pub fn fail_next(&self, err: MungeError) {
+        self.lock().failures.push_back(err);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn resolve_ttl(&self, ttl: i32) -> u32 {
+        match ttl {
+            0 => self.default_ttl,
+            ttl if ttl < 0 => self.max_ttl,
+            ttl => (ttl as u32).min(self.max_ttl),
+        }
+    }
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        MockBackend::new()
+    }
+}
+
+impl MockState {
+    fn now(&self) -> DateTime<Utc> {
+        self.now.unwrap_or_else(Utc::now)
+    }
+}
+
+impl MungeBackend for MockBackend {
+    fn encode_bytes(&self, payload: &[u8], ctx: Option<&Context>) -> Result<String, Error> {
+        let mut minted = Minted {
+            cred: Credential {
+                uid: self.uid,
+                gid: self.gid,
+                message: payload.to_vec(),
+            },
+            encode_time: DateTime::default(),
+            ttl: self.default_ttl,
+            cipher: MungeCipher::Aes128,
+            mac: MungeMac::SHA256,
+            zip: MungeZip::None,
+            uid_restriction: ANY_ID,
+            gid_restriction: ANY_ID,
+        };
+        if let Some(ctx) = ctx {
+            minted.ttl = self.resolve_ttl(ctx.ttl()?);
+            minted.cipher = match ctx.cipher()? {
+                MungeCipher::Default => MungeCipher::Aes128,
+                cipher => cipher,
+            };
+            minted.mac = match ctx.mac()? {
+                MungeMac::Default => MungeMac::SHA256,
+                mac => mac,
+            };
+            minted.zip = match ctx.zip()? {
+                MungeZip::Default => MungeZip::None,
+                zip => zip,
+            };
+            minted.uid_restriction = ctx.uid_restriction()?;
+            minted.gid_restriction = ctx.gid_restriction()?;
+        }
+
+        let mut state = self.lock();
+        if let Some(err) = state.failures.pop_front() {
+            return Err(Error::MungeError(err, err.to_string()));
+        }
+
+        minted.encode_time = state.now();
+        let cred = format!("MUNGE:mock-{:016x}:", state.next_id);
+        state.next_id += 1;
+        state.minted.insert(cred.clone(), minted);
+        Ok(cred)
+    }
+
+    fn decode_bytes(
+        &self,
+        encoded_msg: &str,
+        _ctx: Option<&Context>,
+    ) -> Result<DecodedCredential<Vec<u8>>, Error> {
+        let mut state = self.lock();
+        let now = state.now();
+        let injected = state.failures.pop_front();
+
+        let Some(minted) = state.minted.get(encoded_msg).cloned() else {
+            let err = injected.unwrap_or(MungeError::BadCred);
+            return Err(Error::MungeError(err, err.to_string()));
+        };
+
+        let err = injected.or_else(|| {
+            if (minted.uid_restriction != ANY_ID && minted.uid_restriction != self.uid)
+                || (minted.gid_restriction != ANY_ID && minted.gid_restriction != self.gid)
+            {
+                Some(MungeError::CredUnauthorized)
+            } else if now < minted.encode_time {
+                Some(MungeError::CredRewound)
+            } else if now > minted.encode_time + Duration::seconds(minted.ttl as i64) {
+                Some(MungeError::CredExpired)
+            } else if !state.decoded.insert(encoded_msg.to_string()) {
+                Some(MungeError::CredReplayed)
+            } else {
+                None
+            }
+        });
+
+        let cred = DecodedCredential {
+            uid: minted.cred.uid,
+            gid: minted.cred.gid,
+            message: minted.cred.message,
+            encode_time: minted.encode_time,
+            decode_time: now,
+            addr4: self.addr4,
+            cipher: minted.cipher,
+            mac: minted.mac,
+            zip: minted.zip,
+            ttl: minted.ttl as i32,
+        };
+        match err {
+            None => Ok(cred),
+            Some(
+                err
+                @ (MungeError::CredExpired | MungeError::CredRewound | MungeError::CredReplayed),
+            ) => Err(Error::CredentialRejected(err, err.to_string(), cred)),
+            Some(err) => Err(Error::MungeError(err, err.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod mock_tests {
+    use std::net::Ipv4Addr;
+
+    use chrono::{DateTime, Duration};
+
+    use crate::{
+        backend::MungeBackend,
+        ctx::Context,
+        enums::{Error, MungeError},
+        mock::MockBackend,
+        MungeCipher,
+    };
+
+    fn rejection(res: Result<impl std::fmt::Debug, Error>) -> Option<MungeError> {
+        res.unwrap_err().munge_error().copied()
+    }
+
+    #[test]
+    fn encode_decode() {
+        let mock = MockBackend::new()
+            .with_uid(1000)
+            .with_gid(100)
+            .with_addr4(Ipv4Addr::new(10, 0, 0, 7))
+            .with_max_ttl(60);
+        let mut ctx = Context::new();
+        ctx.set_ttl(600)
+            .unwrap()
+            .set_cipher(MungeCipher::Aes256)
+            .unwrap();
+
+        let cred = mock.encode("Mock payload", Some(&ctx)).unwrap();
+        let decoded = mock.decode(&cred, None).unwrap();
+
+        assert_eq!(decoded.message, "Mock payload");
+        assert_eq!((decoded.uid, decoded.gid), (1000, 100));
+        assert_eq!(decoded.ttl, 60);
+        assert_eq!(decoded.addr4, Ipv4Addr::new(10, 0, 0, 7));
+        assert_eq!(decoded.cipher, MungeCipher::Aes256);
+    }
+
+    #[test]
+    fn expired_and_rewound() {
+        let mock = MockBackend::new().with_default_ttl(10);
+        mock.set_time(DateTime::from_timestamp(1_700_000_000, 0).unwrap());
+        let cred = mock.encode("", None).unwrap();
+
+        mock.advance(Duration::seconds(-1));
+        assert_eq!(
+            rejection(mock.decode(&cred, None)),
+            Some(MungeError::CredRewound)
+        );
+
+        mock.advance(Duration::seconds(12));
+        let err = mock.decode_bytes(&cred, None).unwrap_err();
+        assert_eq!(err.munge_error(), Some(&MungeError::CredExpired));
+        let rejected = err.rejected_credential().unwrap();
+        assert_eq!(rejected.uid, unsafe { libc::getuid() });
+        assert_eq!(rejected.encode_time.timestamp(), 1_700_000_000);
+        assert_eq!(rejected.ttl, 10);
+    }
+
+    #[test]
+    fn replayed() {
+        let mock = MockBackend::new();
+        let cred = mock.encode("once", None).unwrap();
+
+        assert!(mock.decode(&cred, None).is_ok());
+        assert_eq!(
+            rejection(mock.decode(&cred, None)),
+            Some(MungeError::CredReplayed)
+        );
+    }
+
+    #[test]
+    fn unauthorized() {
+        let mock = MockBackend::new().with_uid(1000);
+        let mut ctx = Context::new();
+        ctx.set_uid_restriction(2000).unwrap();
+
+        let cred = mock.encode("not for you", Some(&ctx)).unwrap();
+        assert_eq!(
+            rejection(mock.decode(&cred, None)),
+            Some(MungeError::CredUnauthorized)
+        );
+    }
+
+    #[test]
+    fn injected_failures() {
+        let mock = MockBackend::new();
+        mock.fail_next(MungeError::Socket);
+        assert_eq!(rejection(mock.encode("", None)), Some(MungeError::Socket));
+
+        let cred = mock.encode("", None).unwrap();
+        mock.fail_next(MungeError::CredReplayed);
+        let err = mock.decode(&cred, None).unwrap_err();
+        assert!(err.rejected_credential().is_some());
+
+        assert_eq!(
+            rejection(mock.decode("MUNGE:bogus:", None)),
+            Some(MungeError::BadCred)
+        );
+    }
+}
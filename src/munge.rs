@@ -1,18 +1,24 @@
 use std::{
-    ffi::{self, CStr, CString},
-    ptr,
+    ffi::{self, CString},
+    net::Ipv4Addr,
     str::Utf8Error,
 };
 
+use chrono::DateTime;
+
 use crate::{
-    credential::Credential,
+    credential::{Credential, DecodedCredential},
     ctx::Context,
-    enums::{self, MungeError},
+    enums::{self, MungeCipher, MungeError, MungeMac, MungeZip},
     ffi as c,
+    retry::retry,
+    sys,
 };
 
 /// Encodes the given message and returns a base64 encoded credential string.
 ///
+/// This is a convenience wrapper around [`encode_bytes`] for UTF-8 payloads.
+///
 /// # Arguments
 ///
 /// * `msg` - The message to be included with the encoded credential.
@@ -20,9 +26,7 @@ use crate::{
 ///
 /// # Errors
 ///
-/// Returns an `enums::Error` if:
-/// - The message cannot be converted to a C string (contains an internal null byte).
-/// - The encoding process fails.
+/// Returns an `enums::Error` if the encoding process fails.
 ///
 /// # Example
 ///
@@ -35,43 +39,61 @@ use crate::{
 /// }
 /// ```
 pub fn encode(msg: &str, ctx: Option<&Context>) -> Result<String, enums::Error> {
-    let mut cred: *mut ffi::c_char = ptr::null_mut();
-    let len: ffi::c_int = msg.len() as i32;
-    let buf: *const ffi::c_void = CString::new(msg)?.into_raw() as *const ffi::c_void;
-
-    let err: u32 = if let Some(ctx) = ctx {
-        unsafe { c::munge_encode(&mut cred, ctx.ctx, buf, len) }
-    } else {
-        unsafe { c::munge_encode(&mut cred, ptr::null_mut(), buf, len) }
-    };
+    encode_bytes(msg.as_bytes(), ctx)
+}
 
-    if err != 0 {
-        Err(enums::Error::MungeError(
-            MungeError::from_u32(err),
-            if let Some(ctx) = ctx {
-                match ctx.str_error()? {
-                    Some(s) => s,
-                    None => "No error description available.".to_string(),
-                }
-            } else {
-                match str_error(err)? {
-                    Some(s) => s,
-                    None => "No error description available.".to_string(),
-                }
-            },
-        ))
-    } else {
-        let resp = Ok(unsafe { CStr::from_ptr(cred as *const i8) }
-            .to_str()?
-            .to_string());
-        unsafe { libc::free(cred as *mut ffi::c_void) };
-        resp
-    }
+/// Encodes the given binary payload and returns a base64 encoded credential string.
+///
+/// Unlike [`encode`], the payload may contain arbitrary bytes, including NUL bytes
+/// and non-UTF-8 data.
+///
+/// # Arguments
+///
+/// * `payload` - The bytes to be included with the encoded credential.
+/// * `ctx` - An optional reference to a `Context`. If no context is provided, defaults will be used.
+///
+/// # Errors
+///
+/// Returns an `enums::Error` if:
+/// - The payload is longer than `c_int::MAX` bytes.
+/// - The encoding process fails.
+///
+/// # Example
+///
+/// ```ignore
+/// let encoded = encode_bytes(&[0xde, 0xad, 0x00, 0xbe, 0xef], None)?;
+/// ```
+pub fn encode_bytes(payload: &[u8], ctx: Option<&Context>) -> Result<String, enums::Error> {
+    check_length(payload)?;
+
+    retry(ctx, || {
+        encode_result(sys::encode(ctx.map(|ctx| &ctx.ctx), payload))
+    })
 }
 
 /// Decodes the provided base64 encoded string.
 /// If no context is provided the default values are used.
 ///
+/// This is a convenience wrapper around [`decode_bytes`] for UTF-8 payloads.
+///
+/// * `encoded_msg` - The base64 encoded credential string to decode.
+/// * `ctx` - An optional reference to a [`Context`]. If no context is provided, defaults will be used.
+///
+/// # Errors
+///
+/// This will return an error thrown by munge, when the provided `encoded_msg` is invalid ie.
+/// the bytes provided contain an internal 0 byte [`std::ffi::NulError`], or when the payload
+/// is not valid UTF-8.
+pub fn decode(encoded_msg: String, ctx: Option<&Context>) -> Result<Credential, enums::Error> {
+    Ok(Credential::try_from(decode_bytes(&encoded_msg, ctx)?)?)
+}
+
+/// Decodes the provided base64 encoded string into a credential with a binary payload.
+/// If no context is provided the default values are used.
+///
+/// The returned payload has exactly the length reported by `munge_decode`, so it may
+/// contain NUL bytes and non-UTF-8 data.
+///
 /// * `encoded_msg` - The base64 encoded credential string to decode.
 /// * `ctx` - An optional reference to a [`Context`]. If no context is provided, defaults will be used.
 ///
@@ -79,60 +101,169 @@ pub fn encode(msg: &str, ctx: Option<&Context>) -> Result<String, enums::Error>
 ///
 /// This will return an error thrown by munge or when the provided `encoded_msg` is invalid ie.
 /// the bytes provided contain an internal 0 byte. [`std::ffi::NulError`]
-pub fn decode(encoded_msg: String, ctx: Option<&Context>) -> Result<Credential, enums::Error> {
-    let cred: *mut ffi::c_char = CString::new(encoded_msg)?.into_raw();
-    let mut dmsg: *mut ffi::c_void = ptr::null_mut();
-    let mut len: ffi::c_int = 0;
-    let mut uid: c::uid_t = 0;
-    let mut gid: c::gid_t = 0;
-
-    let err: u32 = if let Some(ctx) = ctx {
-        unsafe { c::munge_decode(cred, ctx.ctx, &mut dmsg, &mut len, &mut uid, &mut gid) }
-    } else {
-        unsafe {
-            c::munge_decode(
-                cred,
-                ptr::null_mut(),
-                &mut dmsg,
-                &mut len,
-                &mut uid,
-                &mut gid,
-            )
-        }
-    };
+///
+/// Expired, rewound and replayed credentials are reported as
+/// [`enums::Error::CredentialRejected`], which still carries the UID, GID, payload and
+/// metadata.
+pub fn decode_bytes(
+    encoded_msg: &str,
+    ctx: Option<&Context>,
+) -> Result<Credential<Vec<u8>>, enums::Error> {
+    let (cred, _) = decode_with_metadata(encoded_msg, ctx)?;
+    Ok(cred)
+}
 
-    if err != 0 {
-        Err(enums::Error::MungeError(
+/// Decodes the credential and returns the metadata reported for this very call.
+fn decode_with_metadata(
+    encoded_msg: &str,
+    ctx: Option<&Context>,
+) -> Result<(Credential<Vec<u8>>, sys::Metadata), enums::Error> {
+    let cred: CString = CString::new(encoded_msg)?;
+
+    retry(ctx, || {
+        decode_result(sys::decode(&cred, ctx.map(|ctx| &ctx.ctx)))
+    })
+}
+
+/// Checks that the payload length fits into the `int` used by MUNGE.
+pub(crate) fn check_length(payload: &[u8]) -> Result<(), enums::Error> {
+    if ffi::c_int::try_from(payload.len()).is_err() {
+        return Err(enums::Error::MungeError(
+            MungeError::BadLength,
+            "Payload exceeds the maximum message length.".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Converts the outcome of a low-level encode into the public result.
+pub(crate) fn encode_result(
+    res: Result<CString, sys::EncodeError>,
+) -> Result<String, enums::Error> {
+    match res {
+        Ok(cred) => Ok(cred.to_str()?.to_string()),
+        Err((err, description)) => Err(munge_error(err, description)?),
+    }
+}
+
+/// Converts the outcome of a low-level decode into the public result.
+pub(crate) fn decode_result(
+    decoded: sys::Decoded,
+) -> Result<(Credential<Vec<u8>>, sys::Metadata), enums::Error> {
+    let sys::Decoded {
+        err,
+        description,
+        cred,
+        metadata,
+    } = decoded;
+
+    match err {
+        0 => Ok((cred, metadata)),
+        c::munge_err_EMUNGE_CRED_EXPIRED
+        | c::munge_err_EMUNGE_CRED_REWOUND
+        | c::munge_err_EMUNGE_CRED_REPLAYED => Err(enums::Error::CredentialRejected(
             MungeError::from_u32(err),
-            if let Some(ctx) = ctx {
-                match ctx.str_error()? {
-                    Some(s) => s,
-                    None => "No error description available.".to_string(),
-                }
-            } else {
-                match str_error(err)? {
-                    Some(s) => s,
-                    None => "No error description available.".to_string(),
-                }
-            },
-        ))
-    } else {
-        let resp: String = if !dmsg.is_null() {
-            unsafe { CStr::from_ptr(dmsg as *const i8) }
-                .to_str()?
-                .to_string()
-        } else {
-            "".to_string()
-        };
-        unsafe { libc::free(dmsg) };
-        Ok(Credential {
-            message: resp,
-            uid,
-            gid,
-        })
+            error_description(err, description)?,
+            decoded_credential(cred, metadata)?,
+        )),
+        _ => Err(munge_error(err, description)?),
     }
 }
 
+/// Decodes the provided base64 encoded string and returns the credential together
+/// with its metadata (encode/decode time, origin address, cipher, MAC, zip and TTL).
+///
+/// This is a convenience wrapper around [`decode_full_bytes`] for UTF-8 payloads.
+///
+/// * `encoded_msg` - The base64 encoded credential string to decode.
+/// * `ctx` - An optional reference to a [`Context`]. If no context is provided, defaults will be used.
+///
+/// # Errors
+///
+/// Returns an error if decoding fails, if the metadata is invalid, or if the payload is
+/// not valid UTF-8.
+///
+/// # Example
+///
+/// ```ignore
+/// let decoded = munge::decode_full(&encoded, None)?;
+/// println!("{} encoded by {} at {}", decoded.uid, decoded.addr4, decoded.encode_time);
+/// ```
+pub fn decode_full(
+    encoded_msg: &str,
+    ctx: Option<&Context>,
+) -> Result<DecodedCredential, enums::Error> {
+    Ok(DecodedCredential::try_from(decode_full_bytes(
+        encoded_msg,
+        ctx,
+    )?)?)
+}
+
+/// Decodes the provided base64 encoded string into a credential with a binary payload
+/// and its metadata.
+///
+/// * `encoded_msg` - The base64 encoded credential string to decode.
+/// * `ctx` - An optional reference to a [`Context`]. If no context is provided, defaults will be used.
+///
+/// The metadata is the one reported for this call, also when the context is shared
+/// with other threads decoding at the same time.
+///
+/// # Errors
+///
+/// Returns an error if decoding fails or if the metadata is invalid.
+pub fn decode_full_bytes(
+    encoded_msg: &str,
+    ctx: Option<&Context>,
+) -> Result<DecodedCredential<Vec<u8>>, enums::Error> {
+    let (cred, metadata) = decode_with_metadata(encoded_msg, ctx)?;
+    decoded_credential(cred, metadata)
+}
+
+/// Combines a decoded credential with the metadata reported for it.
+pub(crate) fn decoded_credential(
+    cred: Credential<Vec<u8>>,
+    metadata: sys::Metadata,
+) -> Result<DecodedCredential<Vec<u8>>, enums::Error> {
+    let time = |secs| DateTime::from_timestamp(secs, 0).ok_or(enums::Error::InvalidTime);
+
+    Ok(DecodedCredential {
+        uid: cred.uid,
+        gid: cred.gid,
+        message: cred.message,
+        encode_time: time(metadata.encode_time)?,
+        decode_time: time(metadata.decode_time)?,
+        addr4: Ipv4Addr::from(metadata.addr4),
+        cipher: MungeCipher::try_from(metadata.cipher as u32)?,
+        mac: MungeMac::try_from(metadata.mac as u32)?,
+        zip: MungeZip::try_from(metadata.zip as u32)?,
+        ttl: metadata.ttl,
+    })
+}
+
+/// Builds an [`enums::Error::MungeError`] for the given error code.
+pub(crate) fn munge_error(
+    err: u32,
+    description: Option<CString>,
+) -> Result<enums::Error, Utf8Error> {
+    Ok(enums::Error::MungeError(
+        MungeError::from_u32(err),
+        error_description(err, description)?,
+    ))
+}
+
+/// Describes the given error code.
+///
+/// The description reported by the call is used if there is one, otherwise the generic
+/// MUNGE error string.
+fn error_description(err: u32, description: Option<CString>) -> Result<String, Utf8Error> {
+    let description = match description {
+        Some(description) => Some(description.to_str()?.to_string()),
+        None => str_error(err)?,
+    };
+
+    Ok(description.unwrap_or_else(|| "No error description available.".to_string()))
+}
+
 /// Retrieves a human-readable error message associated with a given MUNGE error code.
 ///
 /// This function calls the MUNGE library's `munge_strerror` function to obtain an
@@ -161,14 +292,12 @@ pub fn decode(encoded_msg: String, ctx: Option<&Context>) -> Result<Credential,
 /// }
 /// ```
 pub(crate) fn str_error(e: u32) -> Result<Option<String>, Utf8Error> {
-    let err: *const libc::c_char = unsafe { crate::ffi::munge_strerror(e) };
-
-    if err.is_null() {
+    let Some(err) = sys::strerror(e) else {
         return Ok(None); // No error condition
-    }
+    };
 
     // The conversion from CStr to &str and from &str to String
-    let out_err = Some(unsafe { CStr::from_ptr(err) }.to_str()?);
+    let out_err = Some(err.to_str()?);
 
     // Return the error message if parsing was successful, otherwise None
     Ok(out_err.map(|s| s.to_string()))
@@ -178,7 +307,7 @@ pub(crate) fn str_error(e: u32) -> Result<Option<String>, Utf8Error> {
 mod munge_tests {
     use crate::{
         ctx::Context,
-        enums::{MungeMac, MungeOption},
+        enums::{MungeError, MungeMac, MungeOption},
         munge::{self, str_error},
     };
 
@@ -212,4 +341,51 @@ mod munge_tests {
         let res = munge::decode(cred, None).expect("Failed to decode");
         println!("Result: {:?}", res);
     }
+
+    #[test]
+    fn encode_decode_bytes() {
+        let payload: &[u8] = &[0x00, 0xff, 0x42, 0x00, 0xc3, 0x28, 0x00];
+        let cred = munge::encode_bytes(payload, None).expect("Failed to encode");
+
+        let res = munge::decode_bytes(&cred, None).expect("Failed to decode");
+        assert_eq!(res.message, payload);
+    }
+
+    #[test]
+    fn encode_decode_empty_bytes() {
+        let cred = munge::encode_bytes(&[], None).expect("Failed to encode");
+
+        let res = munge::decode_bytes(&cred, None).expect("Failed to decode");
+        assert!(res.message.is_empty());
+    }
+
+    #[test]
+    fn encode_decode_full() {
+        let mut ctx = Context::new();
+        ctx.set_ttl(120).expect("Failed to set TTL");
+        let cred = munge::encode("Metadata", Some(&ctx)).expect("Failed to encode");
+
+        let res = munge::decode_full(&cred, None).expect("Failed to decode");
+        assert_eq!(res.message, "Metadata");
+        assert_eq!(res.ttl, 120);
+        assert!(res.encode_time <= res.decode_time);
+        println!("Result: {:?}", res);
+    }
+
+    #[test]
+    fn decode_expired() {
+        let mut ctx = Context::new();
+        ctx.set_ttl(1).expect("Failed to set TTL");
+        let cred = munge::encode("Too late", Some(&ctx)).expect("Failed to encode");
+        std::thread::sleep(std::time::Duration::from_secs(3));
+
+        let err = munge::decode(cred, None).expect_err("Credential should be expired");
+        assert!(matches!(err.munge_error(), Some(MungeError::CredExpired)));
+        let partial = err
+            .rejected_credential()
+            .expect("Missing partial credential");
+        assert_eq!(partial.message, b"Too late");
+        assert_eq!(partial.uid, unsafe { libc::getuid() });
+        assert_eq!(partial.ttl, 1);
+    }
 }
@@ -0,0 +1,323 @@
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString, OsStr},
+    hash::Hash,
+    io,
+    mem::MaybeUninit,
+    os::unix::ffi::OsStrExt,
+    path::PathBuf,
+    ptr,
+    sync::{Mutex, MutexGuard, OnceLock, PoisonError},
+    time::{Duration, Instant},
+};
+
+use crate::{credential::Credential, enums::Error};
+
+/// Time entries of the global cache are kept, see [`NssCache::global`].
+const DEFAULT_TTL: Duration = Duration::from_secs(60);
+
+/// User account resolved through NSS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserInfo {
+    /// User ID of the account.
+    pub uid: libc::uid_t,
+    /// Name of the user.
+    pub name: String,
+    /// Primary group ID of the account.
+    pub gid: libc::gid_t,
+    /// Home directory of the user.
+    pub home_dir: PathBuf,
+    /// IDs of all groups of the user, including the primary group.
+    pub groups: Vec<libc::gid_t>,
+}
+
+/// Cache of users and groups resolved through NSS (`getpwuid_r`, `getgrgid_r` and
+/// `getgrouplist`).
+///
+/// Lookups go to NSS, which may ask a directory service like LDAP or SSSD, at most once
+/// per TTL and ID. Unknown IDs are cached as well.
+///
+/// The helpers on [`Credential`] use the [global cache](NssCache::global).
+#[derive(Debug)]
+pub struct NssCache {
+    ttl: Duration,
+    users: Mutex<HashMap<libc::uid_t, Cached<Option<UserInfo>>>>,
+    groups: Mutex<HashMap<libc::gid_t, Cached<Option<String>>>>,
+}
+
+#[derive(Debug)]
+struct Cached<T> {
+    value: T,
+    fetched: Instant,
+}
+
+impl NssCache {
+    /// Creates an empty cache keeping entries for the given time.
+    pub fn new(ttl: Duration) -> Self {
+        NssCache {
+            ttl,
+            users: Mutex::new(HashMap::new()),
+            groups: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the cache shared by the helpers on [`Credential`], keeping entries for
+    /// 60 seconds.
+    pub fn global() -> &'static NssCache {
+        static GLOBAL: OnceLock<NssCache> = OnceLock::new();
+        GLOBAL.get_or_init(|| NssCache::new(DEFAULT_TTL))
+    }
+
+    /// Looks up the user account with the given UID, returning `None` if there is none.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if NSS fails.
+    pub fn user(&self, uid: libc::uid_t) -> Result<Option<UserInfo>, Error> {
+        self.cached(&self.users, uid, lookup_user)
+    }
+
+    /// Looks up the name of the group with the given GID, returning `None` if there is
+    /// none.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if NSS fails.
+    pub fn group_name(&self, gid: libc::gid_t) -> Result<Option<String>, Error> {
+        self.cached(&self.groups, gid, lookup_group_name)
+    }
+
+    /// Removes all entries, eg. after accounts were changed.
+    pub fn clear(&self) {
+        lock(&self.users).clear();
+        lock(&self.groups).clear();
+    }
+
+    fn cached<K: Eq + Hash + Copy, V: Clone>(
+        &self,
+        map: &Mutex<HashMap<K, Cached<V>>>,
+        key: K,
+        lookup: impl FnOnce(K) -> Result<V, Error>,
+    ) -> Result<V, Error> {
+        if let Some(entry) = lock(map).get(&key) {
+            if entry.fetched.elapsed() < self.ttl {
+                return Ok(entry.value.clone());
+            }
+        }
+
+        // NSS is not called under the lock, concurrent misses may look up the same ID.
+        let value = lookup(key)?;
+        lock(map).insert(
+            key,
+            Cached {
+                value: value.clone(),
+                fetched: Instant::now(),
+            },
+        );
+        Ok(value)
+    }
+}
+
+impl Default for NssCache {
+    /// Creates an empty cache keeping entries for 60 seconds.
+    fn default() -> Self {
+        NssCache::new(DEFAULT_TTL)
+    }
+}
+
+impl<T> Credential<T> {
+    /// Resolves the user account of the credential's UID, see [`NssCache::user`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if NSS fails.
+    pub fn user(&self) -> Result<Option<UserInfo>, Error> {
+        NssCache::global().user(self.uid)
+    }
+
+    /// Resolves the name of the credential's user.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if NSS fails.
+    pub fn user_name(&self) -> Result<Option<String>, Error> {
+        Ok(self.user()?.map(|user| user.name))
+    }
+
+    /// Resolves the name of the credential's group.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if NSS fails.
+    pub fn group_name(&self) -> Result<Option<String>, Error> {
+        NssCache::global().group_name(self.gid)
+    }
+
+    /// Resolves the home directory of the credential's user.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if NSS fails.
+    pub fn home_dir(&self) -> Result<Option<PathBuf>, Error> {
+        Ok(self.user()?.map(|user| user.home_dir))
+    }
+
+    /// Resolves the IDs of all groups of the credential's user, including the primary
+    /// group of the account. Returns an empty list if the user is unknown.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if NSS fails.
+    pub fn supplementary_groups(&self) -> Result<Vec<libc::gid_t>, Error> {
+        Ok(self.user()?.map(|user| user.groups).unwrap_or_default())
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Outcome of a call to a reentrant NSS lookup.
+enum Lookup {
+    Found,
+    NotFound,
+    /// The buffer was too small and has been grown.
+    Retry,
+}
+
+/// Interprets the return value of a reentrant NSS lookup, growing the buffer if it was
+/// too small.
+fn lookup_result(
+    err: libc::c_int,
+    found: bool,
+    buf: &mut Vec<libc::c_char>,
+) -> Result<Lookup, Error> {
+    match err {
+        0 if found => Ok(Lookup::Found),
+        0 | libc::ENOENT | libc::ESRCH => Ok(Lookup::NotFound),
+        libc::ERANGE if buf.len() < 1 << 20 => {
+            buf.resize(buf.len() * 2, 0);
+            Ok(Lookup::Retry)
+        }
+        err => Err(io::Error::from_raw_os_error(err).into()),
+    }
+}
+
+fn lookup_user(uid: libc::uid_t) -> Result<Option<UserInfo>, Error> {
+    let mut pwd = MaybeUninit::<libc::passwd>::uninit();
+    let mut result = ptr::null_mut();
+    let mut buf = vec![0; 1024];
+
+    loop {
+        let err = unsafe {
+            libc::getpwuid_r(
+                uid,
+                pwd.as_mut_ptr(),
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            )
+        };
+        match lookup_result(err, !result.is_null(), &mut buf)? {
+            Lookup::Found => break,
+            Lookup::NotFound => return Ok(None),
+            Lookup::Retry => {}
+        }
+    }
+
+    // SAFETY: `getpwuid_r` filled `pwd`, the strings it points to live in `buf`.
+    let pwd = unsafe { pwd.assume_init_ref() };
+    let name = unsafe { CStr::from_ptr(pwd.pw_name) }.to_owned();
+    let home_dir = unsafe { CStr::from_ptr(pwd.pw_dir) };
+
+    Ok(Some(UserInfo {
+        uid,
+        name: name.to_string_lossy().into_owned(),
+        gid: pwd.pw_gid,
+        home_dir: PathBuf::from(OsStr::from_bytes(home_dir.to_bytes())),
+        groups: lookup_groups(&name, pwd.pw_gid),
+    }))
+}
+
+fn lookup_group_name(gid: libc::gid_t) -> Result<Option<String>, Error> {
+    let mut grp = MaybeUninit::<libc::group>::uninit();
+    let mut result = ptr::null_mut();
+    let mut buf = vec![0; 1024];
+
+    loop {
+        let err = unsafe {
+            libc::getgrgid_r(
+                gid,
+                grp.as_mut_ptr(),
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            )
+        };
+        match lookup_result(err, !result.is_null(), &mut buf)? {
+            Lookup::Found => break,
+            Lookup::NotFound => return Ok(None),
+            Lookup::Retry => {}
+        }
+    }
+
+    // SAFETY: `getgrgid_r` filled `grp`, its name lives in `buf`.
+    let name = unsafe { CStr::from_ptr(grp.assume_init_ref().gr_name) };
+    Ok(Some(name.to_string_lossy().into_owned()))
+}
+
+fn lookup_groups(name: &CString, gid: libc::gid_t) -> Vec<libc::gid_t> {
+    let mut groups: Vec<libc::gid_t> = vec![0; 32];
+    loop {
+        let mut len = groups.len() as libc::c_int;
+        let found =
+            unsafe { libc::getgrouplist(name.as_ptr(), gid, groups.as_mut_ptr(), &mut len) };
+        if found >= 0 {
+            groups.truncate(len.max(0) as usize);
+            return groups;
+        }
+        // `len` holds the number of groups of the user, grow at least by doubling in case
+        // the implementation does not report it.
+        let needed = (len.max(0) as usize).max(groups.len() * 2);
+        groups.resize(needed, 0);
+    }
+}
+
+#[cfg(test)]
+mod nss_tests {
+    use std::{path::PathBuf, time::Duration};
+
+    use crate::{credential::Credential, nss::NssCache};
+
+    #[test]
+    fn root() {
+        let cred = Credential {
+            uid: 0,
+            gid: 0,
+            message: (),
+        };
+
+        assert_eq!(cred.user_name().unwrap().as_deref(), Some("root"));
+        assert_eq!(cred.group_name().unwrap().as_deref(), Some("root"));
+        assert_eq!(cred.home_dir().unwrap(), Some(PathBuf::from("/root")));
+        assert!(cred.supplementary_groups().unwrap().contains(&0));
+    }
+
+    #[test]
+    fn unknown_ids() {
+        let cache = NssCache::new(Duration::from_secs(60));
+
+        assert_eq!(cache.user(4_000_000_000).unwrap(), None);
+        assert_eq!(cache.group_name(4_000_000_000).unwrap(), None);
+    }
+
+    #[test]
+    fn expiry() {
+        let cache = NssCache::new(Duration::ZERO);
+        assert!(cache.user(0).unwrap().is_some());
+        assert!(cache.user(0).unwrap().is_some());
+
+        cache.clear();
+        assert!(cache.users.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,444 @@
+use std::{borrow::Cow, collections::HashSet, time::Duration};
+
+use thiserror::Error;
+
+use crate::{
+    backend::{MungeBackend, Munged},
+    credential::DecodedCredential,
+    ctx::Context,
+    enums::Error,
+    envelope::Envelope,
+    MungeCipher, MungeMac,
+};
+
+/// Reason a [`Policy`] rejected a credential.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PolicyViolation {
+    /// The UID is not in the allowlist.
+    #[error("UID {0} is not allowed")]
+    UidNotAllowed(u32),
+    /// The UID is in the denylist.
+    #[error("UID {0} is denied")]
+    UidDenied(u32),
+    /// The GID is not in the allowlist.
+    #[error("GID {0} is not allowed")]
+    GidNotAllowed(u32),
+    /// The GID is in the denylist.
+    #[error("GID {0} is denied")]
+    GidDenied(u32),
+    /// The credential was encrypted with a cipher weaker than the required one.
+    #[error("Cipher {0} is weaker than the required {1}")]
+    WeakCipher(MungeCipher, MungeCipher),
+    /// The credential was signed with a MAC weaker than the required one.
+    #[error("MAC {0} is weaker than the required {1}")]
+    WeakMac(MungeMac, MungeMac),
+    /// The credential was encoded longer ago than allowed, given in seconds.
+    #[error("Credential is {0} s old, at most {1} s are allowed")]
+    TooOld(u64, u64),
+    /// The payload does not name the required audience.
+    #[error("Credential is not meant for this audience")]
+    AudienceMismatch,
+}
+
+/// Checks applied to decoded credentials by [`decode_with_policy`] and
+/// [`Policy::decode_with`].
+///
+/// An empty policy accepts every credential munged accepts. Denylists take precedence
+/// over allowlists, and the GID lists only apply to the primary GID of the credential.
+///
+/// Ciphers and MACs are ranked by strength: no cipher, then Blowfish and CAST5, then
+/// AES-128, then AES-256; no MAC, then MD5, then SHA-1 and RIPEMD-160, then SHA-256,
+/// then SHA-512.
+///
+/// The audience is the first line of the payload: a credential is meant for audience
+/// `aud` if its payload is `aud` or starts with `aud` followed by a newline. Payloads
+/// in the [`crate::Envelope`] format name their audience in the `aud` claim instead.
+///
+/// # Example
+///
+/// ```ignore
+/// let policy = Policy::new()
+///     .allow_gids([100, 200])
+///     .deny_uids([0])
+///     .min_cipher(MungeCipher::Aes128)
+///     .max_age(Duration::from_secs(60))
+///     .audience("scheduler");
+///
+/// match decode_with_policy(&encoded, &policy, None) {
+///     Ok(cred) => println!("Accepted UID {}", cred.uid),
+///     Err(Error::PolicyViolation(reason, _)) => eprintln!("Rejected: {reason}"),
+///     Err(e) => eprintln!("Decoding failed: {e}"),
+/// }
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Policy {
+    allowed_uids: Option<HashSet<u32>>,
+    denied_uids: HashSet<u32>,
+    allowed_gids: Option<HashSet<u32>>,
+    denied_gids: HashSet<u32>,
+    min_cipher: Option<MungeCipher>,
+    min_mac: Option<MungeMac>,
+    max_age: Option<Duration>,
+    audience: Option<Vec<u8>>,
+}
+
+impl Policy {
+    /// Creates a policy accepting every credential.
+    pub fn new() -> Self {
+        Policy::default()
+    }
+
+    /// Only accepts credentials of the given UIDs, in addition to UIDs allowed before.
+    pub fn allow_uids(mut self, uids: impl IntoIterator<Item = u32>) -> Self {
+        self.allowed_uids
+            .get_or_insert_with(HashSet::new)
+            .extend(uids);
+        self
+    }
+
+    /// Rejects credentials of the given UIDs.
+    pub fn deny_uids(mut self, uids: impl IntoIterator<Item = u32>) -> Self {
+        self.denied_uids.extend(uids);
+        self
+    }
+
+    /// Only accepts credentials of the given GIDs, in addition to GIDs allowed before.
+    pub fn allow_gids(mut self, gids: impl IntoIterator<Item = u32>) -> Self {
+        self.allowed_gids
+            .get_or_insert_with(HashSet::new)
+            .extend(gids);
+        self
+    }
+
+    /// Rejects credentials of the given GIDs.
+    pub fn deny_gids(mut self, gids: impl IntoIterator<Item = u32>) -> Self {
+        self.denied_gids.extend(gids);
+        self
+    }
+
+    /// Rejects credentials encrypted with a cipher weaker than the given one.
+    pub fn min_cipher(mut self, cipher: MungeCipher) -> Self {
+        self.min_cipher = Some(cipher);
+        self
+    }
+
+    /// Rejects credentials signed with a MAC weaker than the given one.
+    pub fn min_mac(mut self, mac: MungeMac) -> Self {
+        self.min_mac = Some(mac);
+        self
+    }
+
+    /// Rejects credentials encoded longer ago than the given duration, as measured by
+    /// munged at a resolution of one second.
+    pub fn max_age(mut self, age: Duration) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+
+    /// Rejects credentials whose payload does not name the given audience.
+    pub fn audience(mut self, audience: impl Into<Vec<u8>>) -> Self {
+        self.audience = Some(audience.into());
+        self
+    }
+
+    /// Checks a decoded credential against the policy.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first [`PolicyViolation`] found.
+    pub fn check<T: AsRef<[u8]>>(
+        &self,
+        cred: &DecodedCredential<T>,
+    ) -> Result<(), PolicyViolation> {
+        if self.denied_uids.contains(&cred.uid) {
+            return Err(PolicyViolation::UidDenied(cred.uid));
+        }
+        if self
+            .allowed_uids
+            .as_ref()
+            .is_some_and(|uids| !uids.contains(&cred.uid))
+        {
+            return Err(PolicyViolation::UidNotAllowed(cred.uid));
+        }
+        if self.denied_gids.contains(&cred.gid) {
+            return Err(PolicyViolation::GidDenied(cred.gid));
+        }
+        if self
+            .allowed_gids
+            .as_ref()
+            .is_some_and(|gids| !gids.contains(&cred.gid))
+        {
+            return Err(PolicyViolation::GidNotAllowed(cred.gid));
+        }
+
+        if let Some(min) = self.min_cipher {
+            if cipher_strength(cred.cipher) < cipher_strength(min) {
+                return Err(PolicyViolation::WeakCipher(cred.cipher, min));
+            }
+        }
+        if let Some(min) = self.min_mac {
+            if mac_strength(cred.mac) < mac_strength(min) {
+                return Err(PolicyViolation::WeakMac(cred.mac, min));
+            }
+        }
+
+        if let Some(max_age) = self.max_age {
+            let age = (cred.decode_time - cred.encode_time)
+                .to_std()
+                .unwrap_or_default();
+            if age > max_age {
+                return Err(PolicyViolation::TooOld(age.as_secs(), max_age.as_secs()));
+            }
+        }
+
+        if let Some(audience) = &self.audience {
+            if payload_audience(cred.message.as_ref()).as_deref() != Some(audience.as_slice()) {
+                return Err(PolicyViolation::AudienceMismatch);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Decodes the credential string with the given backend and checks the credential
+    /// against the policy.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of the backend, or [`Error::PolicyViolation`] with the reason
+    /// and the credential if the policy rejects it.
+    pub fn decode_with<B: MungeBackend + ?Sized>(
+        &self,
+        backend: &B,
+        encoded_msg: &str,
+        ctx: Option<&Context>,
+    ) -> Result<DecodedCredential<Vec<u8>>, Error> {
+        let cred = backend.decode_bytes(encoded_msg, ctx)?;
+        match self.check(&cred) {
+            Ok(()) => Ok(cred),
+            Err(reason) => Err(Error::PolicyViolation(reason, cred)),
+        }
+    }
+}
+
+/// Extracts the audience named by the payload, see [`Policy`].
+fn payload_audience(payload: &[u8]) -> Option<Cow<'_, [u8]>> {
+    if Envelope::is_envelope(payload) {
+        let envelope = Envelope::from_bytes(payload).ok()?;
+        return Some(Cow::Owned(envelope.audience.into_bytes()));
+    }
+    payload.split(|&b| b == b'\n').next().map(Cow::Borrowed)
+}
+
+/// Ranks ciphers by strength, see [`Policy`].
+fn cipher_strength(cipher: MungeCipher) -> u8 {
+    match cipher {
+        MungeCipher::None | MungeCipher::Default => 0,
+        MungeCipher::Blowfish | MungeCipher::Cast5 => 1,
+        MungeCipher::Aes128 => 2,
+        MungeCipher::Aes256 => 3,
+    }
+}
+
+/// Ranks MACs by strength, see [`Policy`].
+fn mac_strength(mac: MungeMac) -> u8 {
+    match mac {
+        MungeMac::None | MungeMac::Default => 0,
+        MungeMac::MD5 => 1,
+        MungeMac::SHA1 | MungeMac::RIPEMD160 => 2,
+        MungeMac::SHA256 => 3,
+        MungeMac::SHA512 => 4,
+    }
+}
+
+/// Decodes the provided base64 encoded string and checks the credential against the
+/// policy.
+///
+/// # Arguments
+///
+/// * `encoded_msg` - The base64 encoded credential string to decode.
+/// * `policy` - The [`Policy`] the credential has to satisfy.
+/// * `ctx` - An optional reference to a [`Context`]. If no context is provided, defaults will be used.
+///
+/// # Errors
+///
+/// Returns the errors of [`decode_bytes_with_policy`], or an error if the payload is
+/// not valid UTF-8.
+pub fn decode_with_policy(
+    encoded_msg: &str,
+    policy: &Policy,
+    ctx: Option<&Context>,
+) -> Result<DecodedCredential, Error> {
+    Ok(DecodedCredential::try_from(decode_bytes_with_policy(
+        encoded_msg,
+        policy,
+        ctx,
+    )?)?)
+}
+
+/// Decodes the provided base64 encoded string into a credential with a binary payload
+/// and checks it against the policy.
+///
+/// The policy is checked against the metadata reported for this call, so the context
+/// may be shared with other threads, eg. behind an `Arc`.
+///
+/// # Arguments
+///
+/// * `encoded_msg` - The base64 encoded credential string to decode.
+/// * `policy` - The [`Policy`] the credential has to satisfy.
+/// * `ctx` - An optional reference to a [`Context`]. If no context is provided, defaults will be used.
+///
+/// # Errors
+///
+/// Returns the errors of [`crate::decode_full_bytes`], or [`Error::PolicyViolation`]
+/// with the reason and the credential if the policy rejects it.
+pub fn decode_bytes_with_policy(
+    encoded_msg: &str,
+    policy: &Policy,
+    ctx: Option<&Context>,
+) -> Result<DecodedCredential<Vec<u8>>, Error> {
+    policy.decode_with(&Munged, encoded_msg, ctx)
+}
+
+#[cfg(test)]
+mod policy_tests {
+    use std::{net::Ipv4Addr, time::Duration};
+
+    use chrono::DateTime;
+
+    use crate::{
+        backend::MungeBackend,
+        credential::DecodedCredential,
+        enums::Error,
+        envelope::Envelope,
+        mock::MockBackend,
+        policy::{Policy, PolicyViolation},
+        MungeCipher, MungeMac, MungeZip,
+    };
+
+    fn credential(message: &str) -> DecodedCredential {
+        let encode_time = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        DecodedCredential {
+            uid: 1000,
+            gid: 100,
+            message: message.to_string(),
+            encode_time,
+            decode_time: encode_time + chrono::Duration::seconds(30),
+            addr4: Ipv4Addr::LOCALHOST,
+            cipher: MungeCipher::Aes128,
+            mac: MungeMac::SHA256,
+            zip: MungeZip::None,
+            ttl: 300,
+        }
+    }
+
+    #[test]
+    fn uids_and_gids() {
+        let cred = credential("");
+
+        assert_eq!(Policy::new().check(&cred), Ok(()));
+        assert_eq!(Policy::new().allow_uids([1000, 1001]).check(&cred), Ok(()));
+        assert_eq!(
+            Policy::new().allow_uids([0]).check(&cred),
+            Err(PolicyViolation::UidNotAllowed(1000))
+        );
+        assert_eq!(
+            Policy::new()
+                .allow_uids([1000])
+                .deny_uids([1000])
+                .check(&cred),
+            Err(PolicyViolation::UidDenied(1000))
+        );
+        assert_eq!(
+            Policy::new().allow_gids([200]).check(&cred),
+            Err(PolicyViolation::GidNotAllowed(100))
+        );
+        assert_eq!(
+            Policy::new().deny_gids([100]).check(&cred),
+            Err(PolicyViolation::GidDenied(100))
+        );
+    }
+
+    #[test]
+    fn cipher_mac_and_age() {
+        let cred = credential("");
+
+        assert_eq!(
+            Policy::new().min_cipher(MungeCipher::Cast5).check(&cred),
+            Ok(())
+        );
+        assert_eq!(
+            Policy::new().min_cipher(MungeCipher::Aes256).check(&cred),
+            Err(PolicyViolation::WeakCipher(
+                MungeCipher::Aes128,
+                MungeCipher::Aes256
+            ))
+        );
+        assert_eq!(
+            Policy::new().min_mac(MungeMac::SHA512).check(&cred),
+            Err(PolicyViolation::WeakMac(MungeMac::SHA256, MungeMac::SHA512))
+        );
+        assert_eq!(
+            Policy::new().max_age(Duration::from_secs(30)).check(&cred),
+            Ok(())
+        );
+        assert_eq!(
+            Policy::new().max_age(Duration::from_secs(10)).check(&cred),
+            Err(PolicyViolation::TooOld(30, 10))
+        );
+    }
+
+    #[test]
+    fn audience() {
+        let policy = Policy::new().audience("scheduler");
+
+        assert_eq!(policy.check(&credential("scheduler")), Ok(()));
+        assert_eq!(policy.check(&credential("scheduler\njob=42")), Ok(()));
+        assert_eq!(
+            policy.check(&credential("scheduler-dev\njob=42")),
+            Err(PolicyViolation::AudienceMismatch)
+        );
+        assert_eq!(
+            policy.check(&credential("")),
+            Err(PolicyViolation::AudienceMismatch)
+        );
+
+        let envelope = |aud: &str| {
+            let payload = Envelope::new(aud, "submit-job", "scheduler").to_bytes();
+            credential(&String::from_utf8(payload.unwrap()).unwrap())
+        };
+        assert_eq!(policy.check(&envelope("scheduler")), Ok(()));
+        assert_eq!(
+            policy.check(&envelope("worker")),
+            Err(PolicyViolation::AudienceMismatch)
+        );
+        assert_eq!(
+            policy.check(&credential("MUNGE-ENVELOPE/1\naud=scheduler\n\n")),
+            Err(PolicyViolation::AudienceMismatch)
+        );
+    }
+
+    #[test]
+    fn decode_policy() {
+        let mock = MockBackend::new().with_uid(1000);
+        let policy = Policy::new().deny_uids([1000]);
+        let cred = mock
+            .encode("worker\nhello", None)
+            .expect("Failed to encode");
+
+        let err = policy.decode_with(&mock, &cred, None).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::PolicyViolation(PolicyViolation::UidDenied(1000), _)
+        ));
+        assert_eq!(err.rejected_credential().unwrap().message, b"worker\nhello");
+
+        let cred = mock
+            .encode("worker\nhello", None)
+            .expect("Failed to encode");
+        let accepted = Policy::new()
+            .audience("worker")
+            .decode_with(&mock, &cred, None);
+        assert_eq!(accepted.unwrap().uid, 1000);
+    }
+}
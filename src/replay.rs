@@ -0,0 +1,541 @@
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs::{self, File, Metadata, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write},
+    os::unix::{fs::MetadataExt, io::AsRawFd},
+    path::{Path, PathBuf},
+    process,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use chrono::{DateTime, Duration, Utc};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    backend::{MungeBackend, Munged},
+    credential::DecodedCredential,
+    ctx::Context,
+    enums::Error,
+};
+
+/// Number of entries below which expired entries are not purged on insert.
+const MIN_COMPACT_LEN: usize = 1024;
+
+type Key = [u8; 32];
+
+/// Application-level replay cache for decoded credentials.
+///
+/// munged only rejects a credential decoded twice by the same daemon. When the same
+/// credential can be presented to several nodes, each node decodes it once with its own
+/// munged. A `ReplayCache` records the SHA-256 digest of every accepted credential until
+/// it expires at its encode time plus TTL, and rejects reuse with
+/// [`Error::ReplayDetected`].
+///
+/// A cache created with [`ReplayCache::new`] only lives in memory. A cache opened with
+/// [`ReplayCache::open`] is kept in a file, which several processes, also on different
+/// nodes, can share through shared storage. Each check holds an exclusive `flock` on a
+/// lock file next to the cache file, named like it with a `.lock` suffix, and first
+/// loads the entries other processes appended since. The shared storage therefore has
+/// to support `flock`, eg. NFS with a running lock manager.
+///
+/// Persistence is append-only: each accepted credential is appended to the file as one
+/// line, and the file is replaced by one without expired entries when the cache is
+/// opened and when expired entries are purged.
+///
+/// # Example
+///
+/// ```ignore
+/// let cache = ReplayCache::open("/var/lib/myapp/replay-cache")?;
+/// match cache.decode(&encoded, None) {
+///     Ok(cred) => println!("Accepted credential of UID {}", cred.uid),
+///     Err(Error::ReplayDetected(cred)) => eprintln!("Replay by UID {}", cred.uid),
+///     Err(e) => eprintln!("Rejected: {e}"),
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct ReplayCache {
+    state: Mutex<CacheState>,
+    lock_file: Option<File>,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    entries: HashMap<Key, DateTime<Utc>>,
+    file: Option<CacheFile>,
+    compact_at: usize,
+}
+
+/// File of a file-backed cache, with how far it was read.
+#[derive(Debug)]
+struct CacheFile {
+    path: PathBuf,
+    read: Option<ReadPosition>,
+}
+
+/// Identity of the cache file and the number of bytes read from it.
+///
+/// The file is replaced when it is compacted, so the device and inode tell whether new
+/// entries can be read from the offset on or the whole file has to be read again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ReadPosition {
+    dev: u64,
+    ino: u64,
+    offset: u64,
+}
+
+impl ReadPosition {
+    /// Position at the end of the file.
+    fn end_of(metadata: &Metadata) -> Self {
+        ReadPosition {
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+            offset: metadata.len(),
+        }
+    }
+}
+
+impl ReplayCache {
+    /// Creates an empty in-memory cache.
+    pub fn new() -> Self {
+        ReplayCache::default()
+    }
+
+    /// Opens a file-backed cache, creating the file and its lock file if they do not
+    /// exist.
+    ///
+    /// Entries of the file that have not expired yet are loaded, and the file is
+    /// rewritten without the expired ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if the file cannot be locked, read or written, or contains
+    /// an invalid entry.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let lock_file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(sibling(&path, ".lock"))?;
+
+        let mut state = CacheState {
+            entries: HashMap::new(),
+            file: Some(CacheFile { path, read: None }),
+            compact_at: 0,
+        };
+        {
+            let _lock = FileLock::acquire(&lock_file)?;
+            state.refresh()?;
+            state.purge(Utc::now())?;
+        }
+
+        Ok(ReplayCache {
+            state: Mutex::new(state),
+            lock_file: Some(lock_file),
+        })
+    }
+
+    /// Returns the number of recorded credentials known to this cache, including
+    /// expired ones that were not purged yet.
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Returns `true` if no credential is recorded.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes expired entries, rewriting the file of a file-backed cache.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if the file cannot be locked, read or rewritten.
+    pub fn purge_expired(&self) -> Result<(), Error> {
+        self.with_state(|state| state.purge(Utc::now()))
+    }
+
+    /// Records a credential string that expires at the given time.
+    ///
+    /// Returns `false` if the credential was already recorded, by this or another
+    /// process sharing the file, and has not expired yet, ie. if it is replayed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if the file cannot be locked or read, or the entry cannot
+    /// be appended to it.
+    pub fn insert(&self, encoded_msg: &str, expires: DateTime<Utc>) -> Result<bool, Error> {
+        self.with_state(|state| state.insert(digest(encoded_msg), expires, Utc::now()))
+    }
+
+    /// Decodes the credential string with munged and rejects it if it was already
+    /// accepted.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ReplayDetected`] if the credential was already accepted, or the
+    /// errors of [`ReplayCache::decode_bytes`].
+    pub fn decode(
+        &self,
+        encoded_msg: &str,
+        ctx: Option<&Context>,
+    ) -> Result<DecodedCredential, Error> {
+        Ok(DecodedCredential::try_from(
+            self.decode_bytes(encoded_msg, ctx)?,
+        )?)
+    }
+
+    /// Decodes the credential string with munged into a credential with a binary
+    /// payload and rejects it if it was already accepted.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ReplayDetected`] if the credential was already accepted, the
+    /// errors of [`crate::decode_full_bytes`], or [`Error::Io`] if the credential cannot
+    /// be recorded in the file.
+    pub fn decode_bytes(
+        &self,
+        encoded_msg: &str,
+        ctx: Option<&Context>,
+    ) -> Result<DecodedCredential<Vec<u8>>, Error> {
+        self.decode_with(&Munged, encoded_msg, ctx)
+    }
+
+    /// Decodes the credential string with the given backend and rejects it if it was
+    /// already accepted.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ReplayDetected`] if the credential was already accepted, the
+    /// errors of the backend, or [`Error::Io`] if the credential cannot be recorded in
+    /// the file.
+    pub fn decode_with<B: MungeBackend + ?Sized>(
+        &self,
+        backend: &B,
+        encoded_msg: &str,
+        ctx: Option<&Context>,
+    ) -> Result<DecodedCredential<Vec<u8>>, Error> {
+        let cred = backend.decode_bytes(encoded_msg, ctx)?;
+        let expires = cred.encode_time + Duration::seconds(cred.ttl.max(0) as i64);
+
+        if self.insert(encoded_msg, expires)? {
+            Ok(cred)
+        } else {
+            Err(Error::ReplayDetected(cred))
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Runs `f` on the state while holding the locks, after loading the entries other
+    /// processes appended to the file.
+    fn with_state<T>(
+        &self,
+        f: impl FnOnce(&mut CacheState) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let mut state = self.lock();
+        let _lock = self.lock_file.as_ref().map(FileLock::acquire).transpose()?;
+        state.refresh()?;
+        f(&mut state)
+    }
+}
+
+impl CacheState {
+    fn insert(
+        &mut self,
+        key: Key,
+        expires: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<bool, Error> {
+        if self
+            .entries
+            .get(&key)
+            .is_some_and(|&recorded| recorded >= now)
+        {
+            return Ok(false);
+        }
+
+        if self.entries.len() >= self.compact_at {
+            self.purge(now)?;
+        }
+
+        if let Some(cache_file) = &mut self.file {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&cache_file.path)?;
+            file.write_all(format_entry(&key, expires).as_bytes())?;
+            // Other processes wait for the lock, so everything up to here was read.
+            cache_file.read = Some(ReadPosition::end_of(&file.metadata()?));
+        }
+        self.entries.insert(key, expires);
+        Ok(true)
+    }
+
+    /// Loads the entries appended to the file since it was last read.
+    fn refresh(&mut self) -> Result<(), Error> {
+        let Some(cache_file) = &mut self.file else {
+            return Ok(());
+        };
+
+        let file = match File::open(&cache_file.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                cache_file.read = None;
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let end = ReadPosition::end_of(&file.metadata()?);
+        let mut offset = match cache_file.read {
+            Some(read)
+                if (read.dev, read.ino) == (end.dev, end.ino) && read.offset <= end.offset =>
+            {
+                read.offset
+            }
+            _ => 0,
+        };
+
+        let mut reader = BufReader::new(file);
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let len = reader.read_line(&mut line)?;
+            if len == 0 {
+                break;
+            }
+            offset += len as u64;
+            let (key, expires) = parse_entry(line.trim_end_matches('\n'))?;
+            self.entries.insert(key, expires);
+        }
+
+        cache_file.read = Some(ReadPosition { offset, ..end });
+        Ok(())
+    }
+
+    fn purge(&mut self, now: DateTime<Utc>) -> Result<(), Error> {
+        self.entries.retain(|_, expires| *expires >= now);
+        self.compact_at = (self.entries.len() * 2).max(MIN_COMPACT_LEN);
+
+        let Some(cache_file) = &mut self.file else {
+            return Ok(());
+        };
+
+        let tmp = sibling(&cache_file.path, &format!(".tmp.{}", process::id()));
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        for (key, expires) in &self.entries {
+            writer.write_all(format_entry(key, *expires).as_bytes())?;
+        }
+        let file = writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &cache_file.path)?;
+        cache_file.read = Some(ReadPosition::end_of(&file.metadata()?));
+        Ok(())
+    }
+}
+
+/// Exclusive `flock` on the lock file of a file-backed cache, released when dropped.
+struct FileLock<'a>(&'a File);
+
+impl<'a> FileLock<'a> {
+    fn acquire(file: &'a File) -> io::Result<Self> {
+        loop {
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+                return Ok(FileLock(file));
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+}
+
+impl Drop for FileLock<'_> {
+    fn drop(&mut self) {
+        unsafe { libc::flock(self.0.as_raw_fd(), libc::LOCK_UN) };
+    }
+}
+
+/// Returns the path of a file next to `path`, named like it with the given suffix.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map_or_else(OsString::new, OsString::from);
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn digest(encoded_msg: &str) -> Key {
+    Sha256::digest(encoded_msg.trim().as_bytes()).into()
+}
+
+/// Formats an entry as a line of the hex encoded digest and the expiry as a Unix
+/// timestamp.
+fn format_entry(key: &Key, expires: DateTime<Utc>) -> String {
+    let hex: String = key.iter().map(|b| format!("{b:02x}")).collect();
+    format!("{hex} {}\n", expires.timestamp())
+}
+
+fn parse_entry(line: &str) -> Result<(Key, DateTime<Utc>), Error> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Invalid entry: {line}"));
+
+    let (hex, expires) = line.split_once(' ').ok_or_else(invalid)?;
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(invalid().into());
+    }
+    let mut key = [0; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|_| invalid())?;
+    }
+    let expires = expires
+        .trim()
+        .parse()
+        .ok()
+        .and_then(|secs| DateTime::from_timestamp(secs, 0))
+        .ok_or_else(invalid)?;
+
+    Ok((key, expires))
+}
+
+#[cfg(test)]
+mod replay_tests {
+    use std::{env, fs, net::Ipv4Addr, path::Path, process};
+
+    use chrono::{Duration, Utc};
+
+    use crate::{
+        backend::MungeBackend,
+        credential::DecodedCredential,
+        ctx::Context,
+        enums::{Error, MungeError},
+        replay::{digest, sibling, ReplayCache},
+        MungeCipher, MungeMac, MungeZip,
+    };
+
+    #[test]
+    fn rejects_reuse() {
+        let cache = ReplayCache::new();
+        let now = Utc::now();
+
+        assert!(cache
+            .insert("MUNGE:a:", now + Duration::seconds(60))
+            .unwrap());
+        assert!(!cache
+            .insert("MUNGE:a:\n", now + Duration::seconds(60))
+            .unwrap());
+        assert!(cache
+            .insert("MUNGE:b:", now + Duration::seconds(60))
+            .unwrap());
+
+        let mut state = cache.lock();
+        let key = digest("MUNGE:a:");
+        assert!(!state.insert(key, now, now).unwrap());
+        assert!(state.insert(key, now, now + Duration::seconds(61)).unwrap());
+    }
+
+    #[test]
+    fn persistence() {
+        let path = env::temp_dir().join(format!("munge-rs-{}.replay", process::id()));
+        let _ = fs::remove_file(&path);
+        let now = Utc::now();
+
+        let cache = ReplayCache::open(&path).unwrap();
+        assert!(cache
+            .insert("MUNGE:a:", now + Duration::seconds(60))
+            .unwrap());
+        assert!(cache
+            .insert("MUNGE:b:", now - Duration::seconds(60))
+            .unwrap());
+        drop(cache);
+
+        let cache = ReplayCache::open(&path).unwrap();
+        assert_eq!(cache.len(), 1);
+        assert!(!cache
+            .insert("MUNGE:a:", now + Duration::seconds(60))
+            .unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+
+        fs::write(&path, "not an entry\n").unwrap();
+        assert!(matches!(ReplayCache::open(&path), Err(Error::Io(_))));
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(sibling(&path, ".lock")).unwrap();
+    }
+
+    #[test]
+    fn shared_file() {
+        let path = env::temp_dir().join(format!("munge-rs-{}-shared.replay", process::id()));
+        let _ = fs::remove_file(&path);
+        let expires = Utc::now() + Duration::seconds(60);
+
+        // Two caches sharing the file stand in for two nodes.
+        let a = ReplayCache::open(&path).unwrap();
+        let b = ReplayCache::open(&path).unwrap();
+        assert!(a.insert("MUNGE:a:", expires).unwrap());
+        assert!(!b.insert("MUNGE:a:", expires).unwrap());
+
+        // Purging replaces the file, so the other cache has to read it again.
+        b.purge_expired().unwrap();
+        assert!(b.insert("MUNGE:b:", expires).unwrap());
+        assert!(!a.insert("MUNGE:b:", expires).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+        assert_eq!(
+            sibling(Path::new("/var/lib/cache.db"), ".tmp.42"),
+            Path::new("/var/lib/cache.db.tmp.42")
+        );
+        assert!(!sibling(&path, &format!(".tmp.{}", process::id())).exists());
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(sibling(&path, ".lock")).unwrap();
+    }
+
+    /// Backend standing in for a different munged on each call, so it never reports
+    /// a replay by itself.
+    struct EveryNode;
+
+    impl MungeBackend for EveryNode {
+        fn encode_bytes(&self, _payload: &[u8], _ctx: Option<&Context>) -> Result<String, Error> {
+            Err(Error::MungeError(
+                MungeError::Snafu,
+                "EveryNode only decodes credentials.".to_string(),
+            ))
+        }
+
+        fn decode_bytes(
+            &self,
+            _encoded_msg: &str,
+            _ctx: Option<&Context>,
+        ) -> Result<DecodedCredential<Vec<u8>>, Error> {
+            Ok(DecodedCredential {
+                uid: 1000,
+                gid: 100,
+                message: b"once".to_vec(),
+                encode_time: Utc::now(),
+                decode_time: Utc::now(),
+                addr4: Ipv4Addr::LOCALHOST,
+                cipher: MungeCipher::Aes128,
+                mac: MungeMac::SHA256,
+                zip: MungeZip::None,
+                ttl: 300,
+            })
+        }
+    }
+
+    #[test]
+    fn decode_replayed() {
+        let cache = ReplayCache::new();
+
+        let cred = cache.decode_with(&EveryNode, "MUNGE:once:", None).unwrap();
+        assert_eq!(cred.message, b"once");
+
+        let err = cache
+            .decode_with(&EveryNode, "MUNGE:once:", None)
+            .unwrap_err();
+        assert!(matches!(err, Error::ReplayDetected(_)));
+        assert_eq!(err.rejected_credential().unwrap().uid, 1000);
+    }
+}
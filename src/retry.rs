@@ -0,0 +1,288 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{ctx::Context, enums::Error};
+
+/// Policy for retrying encode and decode calls that failed to reach munged.
+///
+/// Only errors for which [`Error::is_retryable`] holds are retried, ie. transport errors
+/// ([`MungeError::Socket`](crate::MungeError::Socket) and
+/// [`MungeError::Timeout`](crate::MungeError::Timeout)), eg. while munged restarts.
+/// Credential rejections and all other errors are returned right away. When the policy
+/// gives up, the last error is wrapped in [`Error::RetriesExhausted`] with the number of
+/// attempts made.
+///
+/// The delay before the n-th retry is `initial_backoff * multiplier^(n - 1)`, capped at
+/// `max_backoff` and reduced by a random fraction of up to `jitter` so that clients do
+/// not retry in lockstep.
+///
+/// Note that munged may have decoded a credential before a timeout, in which case the
+/// retried decode reports it as replayed.
+///
+/// # Example
+///
+/// ```ignore
+/// let policy = RetryPolicy::new(5)
+///     .with_backoff(Duration::from_millis(50), Duration::from_secs(1))
+///     .with_deadline(Duration::from_secs(5));
+/// let ctx = Context::builder().retry_policy(policy).build()?;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: f64,
+    deadline: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts, backing off from 100 ms up to 2 s with 20 % jitter and no deadline.
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+            multiplier: 2.0,
+            jitter: 0.2,
+            deadline: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Creates the default policy with the given number of attempts, including the first.
+    pub fn new(max_attempts: u32) -> Self {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the delay before the first retry and the upper bound of the delays.
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Sets the factor the delay grows by after each retry, at least 1.
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Sets the largest fraction, between 0 and 1, by which a delay is randomly reduced.
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the time limit of a call including all its attempts and delays.
+    ///
+    /// No retry is started if its delay would end past the deadline. The asynchronous
+    /// functions also give up on an attempt still running at the deadline, with
+    /// [`Error::RetriesExhausted`] wrapping a [`crate::MungeError::Timeout`]. The synchronous
+    /// ones cannot interrupt a blocking call, so they may overrun it by the last attempt.
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Returns the number of attempts, including the first.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Returns the delay before the next attempt after `attempts` failed ones, or `None`
+    /// if the policy gives up.
+    fn next_delay(&self, attempts: u32, started: Instant) -> Option<Duration> {
+        if attempts >= self.max_attempts {
+            return None;
+        }
+
+        let exponent = attempts.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let backoff = (self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent))
+            .min(self.max_backoff.as_secs_f64());
+        let delay = Duration::from_secs_f64(backoff * (1.0 - self.jitter * random_fraction()));
+
+        match self.deadline {
+            Some(deadline) if started.elapsed() + delay >= deadline => None,
+            _ => Some(delay),
+        }
+    }
+}
+
+/// Returns a random number in `[0, 1)` from the system RNG.
+///
+/// If the RNG fails, the delay is not reduced at all rather than failing the call.
+fn random_fraction() -> f64 {
+    let mut bytes = [0; 8];
+    if getrandom::getrandom(&mut bytes).is_err() {
+        return 0.0;
+    }
+    (u64::from_ne_bytes(bytes) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Runs the operation, retrying the errors for which [`Error::is_retryable`] holds
+/// according to the context's policy.
+pub(crate) fn retry<T>(
+    ctx: Option<&Context>,
+    mut op: impl FnMut() -> Result<T, Error>,
+) -> Result<T, Error> {
+    let Some(policy) = ctx.and_then(Context::retry_policy) else {
+        return op();
+    };
+
+    let started = Instant::now();
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        match op() {
+            Err(e) if e.is_retryable() => match policy.next_delay(attempts, started) {
+                Some(delay) => thread::sleep(delay),
+                None => return Err(Error::RetriesExhausted(attempts, Box::new(e))),
+            },
+            res => return res,
+        }
+    }
+}
+
+/// Asynchronous counterpart of [`retry`], sleeping on the tokio timer and cutting the
+/// whole call off at the deadline of the policy.
+#[cfg(feature = "tokio")]
+pub(crate) async fn retry_async<T, F, Fut>(ctx: Option<&Context>, mut op: F) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, Error>>,
+{
+    let Some(policy) = ctx.and_then(Context::retry_policy) else {
+        return op().await;
+    };
+
+    let started = Instant::now();
+    let mut attempts = 0;
+    let run = async {
+        loop {
+            attempts += 1;
+            match op().await {
+                Err(e) if e.is_retryable() => match policy.next_delay(attempts, started) {
+                    Some(delay) => tokio::time::sleep(delay).await,
+                    None => return Err(Error::RetriesExhausted(attempts, Box::new(e))),
+                },
+                res => return res,
+            }
+        }
+    };
+
+    let Some(deadline) = policy.deadline else {
+        return run.await;
+    };
+    match tokio::time::timeout(deadline, run).await {
+        Ok(res) => res,
+        Err(_) => Err(Error::RetriesExhausted(
+            attempts,
+            Box::new(Error::MungeError(
+                crate::MungeError::Timeout,
+                format!("No response from munged within the deadline of {deadline:?}."),
+            )),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod retry_tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{
+        ctx::Context,
+        enums::{Error, MungeError},
+        retry::{retry, RetryPolicy},
+    };
+
+    fn failing(err: MungeError, attempts: &mut u32) -> Result<(), Error> {
+        *attempts += 1;
+        Err(Error::MungeError(err, err.to_string()))
+    }
+
+    #[test]
+    fn backoff() {
+        let policy = RetryPolicy::new(5)
+            .with_backoff(Duration::from_millis(100), Duration::from_millis(300))
+            .with_jitter(0.0);
+        let started = Instant::now();
+
+        assert_eq!(
+            policy.next_delay(1, started),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(
+            policy.next_delay(2, started),
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(
+            policy.next_delay(4, started),
+            Some(Duration::from_millis(300))
+        );
+        assert_eq!(policy.next_delay(5, started), None);
+
+        let policy = policy.with_deadline(Duration::from_millis(150));
+        assert_eq!(policy.next_delay(2, started), None);
+
+        let policy = RetryPolicy::default().with_jitter(0.5);
+        let delay = policy.next_delay(1, started).unwrap();
+        assert!(delay > Duration::from_millis(50) && delay <= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn retries_transport_errors() {
+        let ctx = Context::builder()
+            .retry_policy(RetryPolicy::new(3).with_backoff(Duration::ZERO, Duration::ZERO))
+            .build()
+            .unwrap();
+
+        let mut attempts = 0;
+        let err = retry(Some(&ctx), || failing(MungeError::Socket, &mut attempts)).unwrap_err();
+        assert_eq!(attempts, 3);
+        assert!(matches!(err, Error::RetriesExhausted(3, _)));
+        assert_eq!(err.munge_error(), Some(&MungeError::Socket));
+
+        let mut attempts = 0;
+        let err = retry(Some(&ctx), || {
+            failing(MungeError::CredReplayed, &mut attempts)
+        });
+        assert_eq!(attempts, 1);
+        assert!(matches!(
+            err,
+            Err(Error::MungeError(MungeError::CredReplayed, _))
+        ));
+
+        let mut attempts = 0;
+        let err = retry(None, || failing(MungeError::Timeout, &mut attempts));
+        assert_eq!(attempts, 1);
+        assert!(matches!(
+            err,
+            Err(Error::MungeError(MungeError::Timeout, _))
+        ));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn deadline_stops_attempt() {
+        let ctx = Context::builder()
+            .retry_policy(RetryPolicy::new(3).with_deadline(Duration::from_millis(50)))
+            .build()
+            .unwrap();
+
+        let started = Instant::now();
+        let err = crate::retry::retry_async(Some(&ctx), std::future::pending::<Result<(), _>>)
+            .await
+            .unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(matches!(err, Error::RetriesExhausted(1, _)));
+        assert_eq!(err.munge_error(), Some(&MungeError::Timeout));
+    }
+}
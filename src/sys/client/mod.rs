@@ -0,0 +1,803 @@
+//! Pure Rust client speaking the munged request/response protocol.
+//!
+//! munged authenticates decode requests by the peer credentials of the Unix domain
+//! socket, so no additional authentication step is needed on the client side.
+
+pub(crate) mod msg;
+
+use std::{
+    ffi::{CStr, CString, OsStr},
+    io::{self, Read, Write},
+    os::unix::{ffi::OsStrExt, net::UnixStream},
+    path::Path,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use crate::{
+    credential::Credential,
+    enums::{MungeEnum, MungeOption},
+    ffi as c,
+};
+
+use super::{Decoded, EncodeError, Metadata};
+use msg::{DecodeRequest, DecodeResponse, EncodeRequest, EncodeResponse, Header, MsgType};
+
+/// Socket used to reach munged when none is configured.
+pub(crate) const DEFAULT_SOCKET: &CStr = c"/var/run/munge/munge.socket.2";
+
+/// Timeout applied to every read and write on the socket.
+pub(crate) const SOCKET_TIMEOUT: Duration = Duration::from_millis(2000);
+
+/// Options and last-request metadata of a context, mirroring `struct munge_ctx`.
+#[derive(Debug, Clone)]
+pub(crate) struct State {
+    cipher: i32,
+    mac: i32,
+    zip: i32,
+    ttl: i32,
+    socket: CString,
+    uid_restriction: u32,
+    gid_restriction: u32,
+    addr4: [u8; 4],
+    encode_time: i64,
+    decode_time: i64,
+    error: Option<CString>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            cipher: c::munge_cipher_MUNGE_CIPHER_DEFAULT as i32,
+            mac: c::munge_mac_MUNGE_MAC_DEFAULT as i32,
+            zip: c::munge_zip_MUNGE_ZIP_DEFAULT as i32,
+            ttl: 0,
+            socket: DEFAULT_SOCKET.to_owned(),
+            uid_restriction: u32::MAX,
+            gid_restriction: u32::MAX,
+            addr4: [0; 4],
+            encode_time: 0,
+            decode_time: 0,
+            error: None,
+        }
+    }
+}
+
+impl State {
+    fn set_error(&mut self, err: u32, description: Option<String>) -> u32 {
+        self.error = match err {
+            0 => None,
+            // munged sends the description with its NUL terminator, like the credential.
+            _ => description
+                .and_then(|d| CString::new(d.trim_end_matches('\0')).ok())
+                .or_else(|| strerror(err).map(CStr::to_owned)),
+        };
+        err
+    }
+}
+
+/// Context state shared behind a lock, since decoding updates it through `&Context`.
+#[derive(Debug, Default)]
+pub(crate) struct RawCtx(Mutex<State>);
+
+impl RawCtx {
+    /// Creates a new context with default options, which cannot fail for the native client.
+    pub(crate) fn try_new() -> Option<Self> {
+        Some(RawCtx::default())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Sets an integer option, returning the MUNGE error code.
+    pub(crate) fn set_u32(&self, option: MungeOption, value: u32) -> u32 {
+        let mut state = self.lock();
+        match option {
+            MungeOption::CipherType => state.cipher = value as i32,
+            MungeOption::MacType => state.mac = value as i32,
+            MungeOption::ZipType => state.zip = value as i32,
+            MungeOption::Ttl => state.ttl = value as i32,
+            MungeOption::UidRestriction => state.uid_restriction = value,
+            MungeOption::GidRestriction => state.gid_restriction = value,
+            _ => return state.set_error(c::munge_err_EMUNGE_BAD_ARG, None),
+        }
+        state.set_error(0, None)
+    }
+
+    /// Sets a string option, returning the MUNGE error code.
+    pub(crate) fn set_str(&self, option: MungeOption, value: &CStr) -> u32 {
+        let mut state = self.lock();
+        match option {
+            MungeOption::Socket => state.socket = value.to_owned(),
+            _ => return state.set_error(c::munge_err_EMUNGE_BAD_ARG, None),
+        }
+        state.set_error(0, None)
+    }
+
+    /// Retrieves a signed integer option.
+    pub(crate) fn get_i32(&self, option: MungeOption) -> Result<i32, u32> {
+        self.get_u32(option).map(|value| value as i32)
+    }
+
+    /// Retrieves an unsigned integer option.
+    ///
+    /// For [`MungeOption::Addr4`] the value holds the address bytes in network order.
+    pub(crate) fn get_u32(&self, option: MungeOption) -> Result<u32, u32> {
+        let mut state = self.lock();
+        match option {
+            MungeOption::CipherType => Ok(state.cipher as u32),
+            MungeOption::MacType => Ok(state.mac as u32),
+            MungeOption::ZipType => Ok(state.zip as u32),
+            MungeOption::Ttl => Ok(state.ttl as u32),
+            MungeOption::Addr4 => Ok(u32::from_ne_bytes(state.addr4)),
+            MungeOption::UidRestriction => Ok(state.uid_restriction),
+            MungeOption::GidRestriction => Ok(state.gid_restriction),
+            _ => Err(state.set_error(c::munge_err_EMUNGE_BAD_ARG, None)),
+        }
+    }
+
+    /// Retrieves a time option as seconds since the Unix epoch.
+    pub(crate) fn get_time(&self, option: MungeOption) -> Result<i64, u32> {
+        let mut state = self.lock();
+        match option {
+            MungeOption::EncodeTime => Ok(state.encode_time),
+            MungeOption::DecodeTime => Ok(state.decode_time),
+            _ => Err(state.set_error(c::munge_err_EMUNGE_BAD_ARG, None)),
+        }
+    }
+
+    /// Retrieves a string option.
+    pub(crate) fn get_str(&self, option: MungeOption) -> Result<CString, u32> {
+        let mut state = self.lock();
+        match option {
+            MungeOption::Socket => Ok(state.socket.clone()),
+            _ => Err(state.set_error(c::munge_err_EMUNGE_BAD_ARG, None)),
+        }
+    }
+
+    /// Retrieves the description of the last error recorded in the context.
+    pub(crate) fn strerror(&self) -> Option<CString> {
+        self.lock().error.clone()
+    }
+}
+
+impl Clone for RawCtx {
+    fn clone(&self) -> Self {
+        let mut state = self.lock().clone();
+        state.error = None;
+        RawCtx(Mutex::new(state))
+    }
+}
+
+/// Retrieves the generic description of a MUNGE error code.
+pub(crate) fn strerror(err: u32) -> Option<&'static CStr> {
+    Some(match err {
+        c::munge_err_EMUNGE_SUCCESS => c"Success",
+        c::munge_err_EMUNGE_SNAFU => c"Internal error",
+        c::munge_err_EMUNGE_BAD_ARG => c"Invalid argument",
+        c::munge_err_EMUNGE_BAD_LENGTH => c"Exceeded maximum message length",
+        c::munge_err_EMUNGE_OVERFLOW => c"Buffer overflow",
+        c::munge_err_EMUNGE_NO_MEMORY => c"Out of memory",
+        c::munge_err_EMUNGE_SOCKET => c"Socket communication error",
+        c::munge_err_EMUNGE_TIMEOUT => c"Socket timeout",
+        c::munge_err_EMUNGE_BAD_CRED => c"Invalid credential format",
+        c::munge_err_EMUNGE_BAD_VERSION => c"Invalid credential version",
+        c::munge_err_EMUNGE_BAD_CIPHER => c"Invalid cipher type",
+        c::munge_err_EMUNGE_BAD_MAC => c"Invalid MAC type",
+        c::munge_err_EMUNGE_BAD_ZIP => c"Invalid compression type",
+        c::munge_err_EMUNGE_BAD_REALM => c"Unrecognized security realm",
+        c::munge_err_EMUNGE_CRED_INVALID => c"Invalid credential",
+        c::munge_err_EMUNGE_CRED_EXPIRED => c"Expired credential",
+        c::munge_err_EMUNGE_CRED_REWOUND => c"Rewound credential",
+        c::munge_err_EMUNGE_CRED_REPLAYED => c"Replayed credential",
+        c::munge_err_EMUNGE_CRED_UNAUTHORIZED => c"Unauthorized credential",
+        _ => return None,
+    })
+}
+
+/// Names of the cipher, MAC and compression types, indexed by their numbers.
+fn enum_names(kind: MungeEnum) -> &'static [&'static CStr] {
+    match kind {
+        MungeEnum::Cipher => &[
+            c"none",
+            c"default",
+            c"blowfish",
+            c"cast5",
+            c"aes128",
+            c"aes256",
+        ],
+        MungeEnum::Mac => &[
+            c"none",
+            c"default",
+            c"md5",
+            c"sha1",
+            c"ripemd160",
+            c"sha256",
+            c"sha512",
+        ],
+        MungeEnum::Zip => &[c"none", c"default", c"bzlib", c"zlib"],
+    }
+}
+
+/// Checks whether the cipher, MAC or compression type is known.
+///
+/// Which types are available depends on how munged was built, which the client cannot
+/// tell, so every known type is reported as supported.
+pub(crate) fn enum_is_valid(kind: MungeEnum, value: u32) -> bool {
+    (value as usize) < enum_names(kind).len()
+}
+
+/// Retrieves the name of the cipher, MAC or compression type.
+pub(crate) fn enum_int_to_str(kind: MungeEnum, value: u32) -> Option<&'static CStr> {
+    enum_names(kind).get(value as usize).copied()
+}
+
+/// Looks up a cipher, MAC or compression type by name, ignoring case, or by number.
+pub(crate) fn enum_str_to_int(kind: MungeEnum, name: &CStr) -> Option<u32> {
+    let names = enum_names(kind);
+    let name = name.to_str().ok()?;
+
+    match names
+        .iter()
+        .position(|n| n.to_bytes().eq_ignore_ascii_case(name.as_bytes()))
+    {
+        Some(value) => Some(value as u32),
+        None => name
+            .parse()
+            .ok()
+            .filter(|&value| enum_is_valid(kind, value)),
+    }
+}
+
+/// Encodes the payload into a credential string.
+pub(crate) fn encode(ctx: Option<&RawCtx>, payload: &[u8]) -> Result<CString, EncodeError> {
+    let res = request_encode(&snapshot(ctx), payload);
+    finish_encode(ctx, res)
+}
+
+/// Decodes the credential string, returning the MUNGE error code along with whatever
+/// munged managed to decode.
+pub(crate) fn decode(cred: &CStr, ctx: Option<&RawCtx>) -> Decoded {
+    let res = request_decode(&snapshot(ctx).socket, cred);
+    finish_decode(ctx, res)
+}
+
+/// Asynchronous counterpart of [`encode`].
+#[cfg(feature = "tokio")]
+pub(crate) async fn encode_async(
+    ctx: Option<&RawCtx>,
+    payload: &[u8],
+) -> Result<CString, EncodeError> {
+    let res = request_encode_async(&snapshot(ctx), payload).await;
+    finish_encode(ctx, res)
+}
+
+/// Asynchronous counterpart of [`decode`].
+#[cfg(feature = "tokio")]
+pub(crate) async fn decode_async(cred: &CStr, ctx: Option<&RawCtx>) -> Decoded {
+    let res = request_decode_async(&snapshot(ctx).socket, cred).await;
+    finish_decode(ctx, res)
+}
+
+/// Copies the options of the context, or the defaults if there is none.
+fn snapshot(ctx: Option<&RawCtx>) -> State {
+    ctx.map_or_else(State::default, |ctx| ctx.lock().clone())
+}
+
+/// Records the outcome of an encode request in the context.
+fn finish_encode(
+    ctx: Option<&RawCtx>,
+    res: Result<CString, (u32, String)>,
+) -> Result<CString, EncodeError> {
+    let (err, description, cred) = match res {
+        Ok(cred) => (0, None, Some(cred)),
+        Err((err, description)) => (err, Some(description), None),
+    };
+
+    let mut state = State::default();
+    state.set_error(err, description);
+    if let Some(ctx) = ctx {
+        ctx.lock().error = state.error.clone();
+    }
+    cred.ok_or((err, state.error))
+}
+
+/// Records the outcome of a decode request in the context.
+fn finish_decode(ctx: Option<&RawCtx>, res: Result<DecodeResponse, (u32, String)>) -> Decoded {
+    let (err, description, rsp) = match res {
+        Ok(rsp) => match rsp.error_num {
+            0 => (0, None, Some(rsp)),
+            err => {
+                let description = String::from_utf8_lossy(&rsp.error_str).into_owned();
+                (err as u32, Some(description), Some(rsp))
+            }
+        },
+        Err((err, description)) => (err, Some(description), None),
+    };
+
+    // The outcome is built apart from the context, which other threads may update.
+    let mut state = State::default();
+    state.set_error(err, description);
+    let metadata = rsp.as_ref().map_or_else(Metadata::default, |rsp| Metadata {
+        cipher: rsp.cipher as i32,
+        mac: rsp.mac as i32,
+        zip: rsp.zip as i32,
+        ttl: rsp.ttl as i32,
+        addr4: rsp.addr.as_slice().try_into().unwrap_or_default(),
+        encode_time: rsp.encode_time as i64,
+        decode_time: rsp.decode_time as i64,
+    });
+
+    if let Some(ctx) = ctx {
+        let mut shared = ctx.lock();
+        if let Some(rsp) = &rsp {
+            shared.cipher = metadata.cipher;
+            shared.mac = metadata.mac;
+            shared.zip = metadata.zip;
+            shared.ttl = metadata.ttl;
+            shared.addr4 = metadata.addr4;
+            shared.encode_time = metadata.encode_time;
+            shared.decode_time = metadata.decode_time;
+            shared.uid_restriction = rsp.auth_uid;
+            shared.gid_restriction = rsp.auth_gid;
+        }
+        shared.error = state.error.clone();
+    }
+
+    Decoded {
+        err,
+        description: state.error,
+        cred: rsp.map_or_else(Credential::default, |rsp| Credential {
+            uid: rsp.cred_uid,
+            gid: rsp.cred_gid,
+            message: rsp.data,
+        }),
+        metadata,
+    }
+}
+
+/// Builds the packed encode request for the context options.
+fn encode_request(state: &State, payload: &[u8]) -> Result<Vec<u8>, (u32, String)> {
+    EncodeRequest {
+        cipher: state.cipher as u8,
+        mac: state.mac as u8,
+        zip: state.zip as u8,
+        realm: Vec::new(),
+        ttl: state.ttl as u32,
+        auth_uid: state.uid_restriction,
+        auth_gid: state.gid_restriction,
+        data: payload.to_vec(),
+    }
+    .pack(0)
+    .map_err(protocol_error)
+}
+
+/// Builds the packed decode request for the credential string.
+fn decode_request(cred: &CStr) -> Result<Vec<u8>, (u32, String)> {
+    // munged expects the credential as a NUL-terminated string.
+    DecodeRequest {
+        data: cred.to_bytes_with_nul().to_vec(),
+    }
+    .pack(0)
+    .map_err(protocol_error)
+}
+
+/// Extracts the credential string from the body of an encode response.
+fn encode_result(body: &[u8]) -> Result<CString, (u32, String)> {
+    let rsp = EncodeResponse::unpack(body).map_err(protocol_error)?;
+    if rsp.error_num != 0 {
+        return Err((
+            rsp.error_num as u32,
+            String::from_utf8_lossy(&rsp.error_str).into_owned(),
+        ));
+    }
+
+    let mut data = rsp.data;
+    while data.last() == Some(&0) {
+        data.pop();
+    }
+    CString::new(data).map_err(|_| {
+        (
+            c::munge_err_EMUNGE_SNAFU,
+            "Received credential with an inner NUL byte".to_string(),
+        )
+    })
+}
+
+fn request_encode(state: &State, payload: &[u8]) -> Result<CString, (u32, String)> {
+    let req = encode_request(state, payload)?;
+    let body = transact(&state.socket, &req, MsgType::EncodeResponse)?;
+    encode_result(&body)
+}
+
+fn request_decode(socket: &CStr, cred: &CStr) -> Result<DecodeResponse, (u32, String)> {
+    let req = decode_request(cred)?;
+    let body = transact(socket, &req, MsgType::DecodeResponse)?;
+    DecodeResponse::unpack(&body).map_err(protocol_error)
+}
+
+#[cfg(feature = "tokio")]
+async fn request_encode_async(state: &State, payload: &[u8]) -> Result<CString, (u32, String)> {
+    let req = encode_request(state, payload)?;
+    let body = transact_async(&state.socket, &req, MsgType::EncodeResponse).await?;
+    encode_result(&body)
+}
+
+#[cfg(feature = "tokio")]
+async fn request_decode_async(socket: &CStr, cred: &CStr) -> Result<DecodeResponse, (u32, String)> {
+    let req = decode_request(cred)?;
+    let body = transact_async(socket, &req, MsgType::DecodeResponse).await?;
+    DecodeResponse::unpack(&body).map_err(protocol_error)
+}
+
+/// Sends a packed request to munged and returns the body of the response.
+fn transact(socket: &CStr, req: &[u8], expected: MsgType) -> Result<Vec<u8>, (u32, String)> {
+    let path = socket_path(socket);
+    let mut stream = UnixStream::connect(path).map_err(|e| connect_error(path, e))?;
+    stream
+        .set_read_timeout(Some(SOCKET_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(SOCKET_TIMEOUT)))
+        .map_err(io_error)?;
+
+    stream.write_all(req).map_err(io_error)?;
+
+    let mut header = [0; msg::HEADER_LEN];
+    stream.read_exact(&mut header).map_err(io_error)?;
+    let header = Header::unpack(&header).map_err(protocol_error)?;
+    header.expect(expected).map_err(protocol_error)?;
+
+    let mut body = vec![0; header.body_len as usize];
+    stream.read_exact(&mut body).map_err(io_error)?;
+    Ok(body)
+}
+
+/// Asynchronous counterpart of [`transact`], dropping the connection when cancelled.
+#[cfg(feature = "tokio")]
+async fn transact_async(
+    socket: &CStr,
+    req: &[u8],
+    expected: MsgType,
+) -> Result<Vec<u8>, (u32, String)> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let exchange = async {
+        let path = socket_path(socket);
+        let mut stream = tokio::net::UnixStream::connect(path)
+            .await
+            .map_err(|e| connect_error(path, e))?;
+
+        stream.write_all(req).await.map_err(io_error)?;
+
+        let mut header = [0; msg::HEADER_LEN];
+        stream.read_exact(&mut header).await.map_err(io_error)?;
+        let header = Header::unpack(&header).map_err(protocol_error)?;
+        header.expect(expected).map_err(protocol_error)?;
+
+        let mut body = vec![0; header.body_len as usize];
+        stream.read_exact(&mut body).await.map_err(io_error)?;
+        Ok(body)
+    };
+
+    tokio::time::timeout(SOCKET_TIMEOUT, exchange)
+        .await
+        .unwrap_or_else(|_| Err(io_error(io::ErrorKind::TimedOut.into())))
+}
+
+fn socket_path(socket: &CStr) -> &Path {
+    Path::new(OsStr::from_bytes(socket.to_bytes()))
+}
+
+fn connect_error(path: &Path, e: io::Error) -> (u32, String) {
+    (
+        c::munge_err_EMUNGE_SOCKET,
+        format!("Failed to connect to \"{}\": {e}", path.display()),
+    )
+}
+
+/// Maps an I/O failure on the socket to a MUNGE error.
+pub(crate) fn io_error(e: io::Error) -> (u32, String) {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => (
+            c::munge_err_EMUNGE_TIMEOUT,
+            "Timed out communicating with munged".to_string(),
+        ),
+        _ => (
+            c::munge_err_EMUNGE_SOCKET,
+            format!("Failed to communicate with munged: {e}"),
+        ),
+    }
+}
+
+/// Maps a malformed message to a MUNGE error.
+pub(crate) fn protocol_error(e: msg::MsgError) -> (u32, String) {
+    (c::munge_err_EMUNGE_SNAFU, e.to_string())
+}
+
+#[cfg(test)]
+mod client_tests {
+    use std::{
+        env,
+        io::{Read, Write},
+        os::unix::net::UnixListener,
+        path::PathBuf,
+        process, thread,
+        time::Duration,
+    };
+
+    use super::msg::{self, DecodeRequest, DecodeResponse, EncodeRequest, EncodeResponse, Header};
+    use crate::{munge, Context, Error, FailoverContext, MungeError, MungeZip, RetryPolicy};
+
+    /// Serves `count` requests on a fresh socket, answering like a trivial munged that
+    /// embeds the payload and the requested TTL in the credential.
+    fn stub_munged(name: &str, count: usize) -> PathBuf {
+        let path = env::temp_dir().join(format!("munge-rs-{}-{name}.sock", process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        thread::spawn(move || {
+            for stream in listener.incoming().take(count) {
+                let mut stream = stream.unwrap();
+                // Requests are answered concurrently, like munged does.
+                thread::spawn(move || {
+                    let mut header = [0; msg::HEADER_LEN];
+                    stream.read_exact(&mut header).unwrap();
+                    let header = Header::unpack(&header).unwrap();
+                    let mut body = vec![0; header.body_len as usize];
+                    stream.read_exact(&mut body).unwrap();
+
+                    let rsp = match header.msg_type {
+                        2 => {
+                            let req = EncodeRequest::unpack(&body).unwrap();
+                            let mut cred = format!("MUNGE:{}:{}:", req.ttl, req.zip).into_bytes();
+                            cred.extend(
+                                req.data
+                                    .iter()
+                                    .flat_map(|b| format!("{b:02x}").into_bytes()),
+                            );
+                            cred.extend_from_slice(b":\0");
+                            EncodeResponse {
+                                data: cred,
+                                ..Default::default()
+                            }
+                            .pack(0)
+                        }
+                        _ => {
+                            let req = DecodeRequest::unpack(&body).unwrap();
+                            // Give concurrent decodes a chance to overlap.
+                            thread::sleep(Duration::from_millis(1));
+                            let cred = String::from_utf8(req.data).unwrap();
+                            let fields: Vec<&str> =
+                                cred.trim_end_matches('\0').split(':').collect();
+                            let ttl: u32 = fields[1].parse().unwrap();
+                            let data = (0..fields[3].len())
+                                .step_by(2)
+                                .map(|i| u8::from_str_radix(&fields[3][i..i + 2], 16).unwrap())
+                                .collect();
+                            DecodeResponse {
+                                error_num: if ttl == 1 { 15 } else { 0 },
+                                error_str: if ttl == 1 {
+                                    b"Expired credential\0".to_vec()
+                                } else {
+                                    Vec::new()
+                                },
+                                zip: fields[2].parse().unwrap(),
+                                ttl,
+                                addr: vec![10, 0, 0, 7],
+                                encode_time: 1_700_000_000,
+                                decode_time: 1_700_000_001,
+                                cred_uid: 1234,
+                                cred_gid: 5678,
+                                data,
+                                ..Default::default()
+                            }
+                            .pack(0)
+                        }
+                    };
+                    stream.write_all(&rsp.unwrap()).unwrap();
+                });
+            }
+        });
+        path
+    }
+
+    #[test]
+    fn encode_decode_stub() {
+        let socket = stub_munged("roundtrip", 2);
+        let mut ctx = Context::new();
+        ctx.set_socket(socket).unwrap();
+        ctx.set_ttl(60).unwrap().set_zip(MungeZip::Zlib).unwrap();
+
+        let cred = munge::encode_bytes(b"bin\0ary", Some(&ctx)).unwrap();
+        let decoded = munge::decode_full_bytes(&cred, Some(&ctx)).unwrap();
+
+        assert_eq!(decoded.message, b"bin\0ary");
+        assert_eq!((decoded.uid, decoded.gid), (1234, 5678));
+        assert_eq!(decoded.ttl, 60);
+        assert_eq!(decoded.zip, MungeZip::Zlib);
+        assert_eq!(decoded.addr4.octets(), [10, 0, 0, 7]);
+        assert_eq!(decoded.encode_time.timestamp(), 1_700_000_000);
+    }
+
+    #[test]
+    fn decode_rejected_stub() {
+        let socket = stub_munged("rejected", 2);
+        let mut ctx = Context::new();
+        ctx.set_socket(socket).unwrap();
+        ctx.set_ttl(1).unwrap();
+
+        let cred = munge::encode("late", Some(&ctx)).unwrap();
+        let err = munge::decode(cred, Some(&ctx)).unwrap_err();
+
+        assert!(matches!(err.munge_error(), Some(MungeError::CredExpired)));
+        let rejected = err.rejected_credential().unwrap();
+        assert_eq!(rejected.message, b"late");
+        assert_eq!(rejected.ttl, 1);
+        assert_eq!(rejected.encode_time.timestamp(), 1_700_000_000);
+        assert_eq!(
+            ctx.str_error().unwrap().as_deref(),
+            Some("Expired credential")
+        );
+    }
+
+    #[test]
+    fn daemon_error_description() {
+        let mut state = super::State::default();
+        state.set_error(15, Some("Expired credential from munged\0".to_string()));
+        assert_eq!(
+            state.error.as_deref(),
+            Some(c"Expired credential from munged")
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn encode_decode_stub_async() {
+        let socket = stub_munged("async", 2);
+        let mut ctx = Context::new();
+        ctx.set_socket(socket).unwrap();
+
+        let cred = crate::encode_async("async", Some(&ctx), None)
+            .await
+            .unwrap();
+        let decoded = crate::decode_async(&cred, Some(&ctx), None).await.unwrap();
+
+        assert_eq!(decoded.message, "async");
+        assert_eq!(ctx.addr4().unwrap().octets(), [10, 0, 0, 7]);
+    }
+
+    #[cfg(feature = "tower")]
+    #[tokio::test]
+    async fn middleware_stub() {
+        use tower_layer::Layer;
+        use tower_service::Service;
+
+        use crate::middleware::test_service::Echo;
+
+        let mut ctx = Context::new();
+        ctx.set_socket(stub_munged("middleware", 1)).unwrap();
+        let mut server = crate::MungeAuthLayer::new(ctx).layer(Echo);
+
+        let req = http::Request::builder()
+            .header(crate::DEFAULT_HEADER, "MUNGE:60:0:6869:")
+            .body(())
+            .unwrap();
+        let res = server.call(req).await.unwrap();
+        assert_eq!(res.into_body(), "1234");
+    }
+
+    #[test]
+    fn failover_stub() {
+        let fallback = stub_munged("fallback", 2);
+        let ctx =
+            FailoverContext::new([PathBuf::from("/nonexistent/munge.socket"), fallback.clone()])
+                .unwrap();
+
+        let cred = ctx.encode("fallback").unwrap();
+        assert_eq!(cred.socket, fallback);
+
+        let decoded = ctx.decode(&cred.value).unwrap();
+        assert_eq!(decoded.value.message, "fallback");
+        assert_eq!(decoded.socket, fallback);
+        assert_eq!(ctx.healthy_socket(), fallback);
+    }
+
+    #[test]
+    fn concurrent_decode_stub() {
+        const THREADS: u32 = 16;
+        const ROUNDS: usize = 64;
+
+        let socket = stub_munged("concurrent", (THREADS as usize) * (3 * ROUNDS + 1));
+        let mut ctx = Context::new();
+        ctx.set_socket(socket.clone()).unwrap();
+        let failover = FailoverContext::new([socket]).unwrap();
+        let policy = crate::Policy::new().allow_uids([1234]);
+
+        // The stub reports the TTL embedded in each credential, so every thread expects
+        // its own TTL back even though all of them share the contexts.
+        let creds: Vec<(u32, String)> = (0..THREADS)
+            .map(|i| {
+                let ttl = 100 + i;
+                let mut ctx = ctx.clone();
+                ctx.set_ttl(ttl).unwrap();
+                (ttl, munge::encode_bytes(&[i as u8], Some(&ctx)).unwrap())
+            })
+            .collect();
+
+        thread::scope(|scope| {
+            for (ttl, cred) in &creds {
+                let (ctx, failover, policy) = (&ctx, &failover, &policy);
+                scope.spawn(move || {
+                    for _ in 0..ROUNDS {
+                        let decoded = munge::decode_full_bytes(cred, Some(ctx)).unwrap();
+                        assert_eq!(decoded.ttl as u32, *ttl);
+                        let served = failover.decode_bytes(cred).unwrap();
+                        assert_eq!(served.value.ttl as u32, *ttl);
+                        let checked =
+                            crate::decode_bytes_with_policy(cred, policy, Some(ctx)).unwrap();
+                        assert_eq!(checked.ttl as u32, *ttl);
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn failover_all_sockets_down() {
+        let ctx =
+            FailoverContext::new(["/nonexistent/munge.socket.1", "/nonexistent/munge.socket.2"])
+                .unwrap();
+
+        let err = ctx.encode("unreachable").unwrap_err();
+        assert!(matches!(err.munge_error(), Some(MungeError::Socket)));
+        assert_eq!(
+            ctx.healthy_socket(),
+            PathBuf::from("/nonexistent/munge.socket.1")
+        );
+    }
+
+    #[test]
+    fn health_check_stub() {
+        let mut ctx = Context::new();
+        ctx.set_socket(stub_munged("health", 2)).unwrap();
+
+        let report = crate::health_check(Some(&ctx));
+        assert!(report.is_healthy());
+        assert_eq!(report.clock_skew, Some(chrono::Duration::seconds(1)));
+
+        ctx.set_socket(PathBuf::from("/nonexistent/munge.socket"))
+            .unwrap();
+        let report = crate::health_check(Some(&ctx));
+        let failure = report.failure.unwrap();
+        assert_eq!(failure.stage, crate::HealthStage::Encode);
+        assert!(matches!(
+            failure.error.munge_error(),
+            Some(MungeError::Socket)
+        ));
+    }
+
+    #[test]
+    fn socket_error() {
+        let mut ctx = Context::new();
+        ctx.set_socket(PathBuf::from("/nonexistent/munge.socket"))
+            .unwrap();
+
+        let err = munge::encode("unreachable", Some(&ctx)).unwrap_err();
+        assert!(matches!(err.munge_error(), Some(MungeError::Socket)));
+    }
+
+    #[test]
+    fn socket_error_retried() {
+        let mut ctx = Context::new();
+        ctx.set_socket(PathBuf::from("/nonexistent/munge.socket"))
+            .unwrap()
+            .set_retry_policy(Some(
+                RetryPolicy::new(3)
+                    .with_backoff(Duration::from_millis(1), Duration::from_millis(1)),
+            ));
+
+        let err = munge::encode("unreachable", Some(&ctx)).unwrap_err();
+        assert!(matches!(err, Error::RetriesExhausted(3, _)));
+        assert!(err.is_transport_error());
+    }
+}
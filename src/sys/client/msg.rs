@@ -0,0 +1,406 @@
+//! Messages exchanged with munged over its Unix domain socket.
+//!
+//! Every message starts with a fixed-size header followed by a body whose layout
+//! depends on the message type. Multi-byte integers are sent in network byte order
+//! and variable-length fields are prefixed with their length.
+
+use std::fmt;
+
+/// Magic number starting every message.
+pub(crate) const MAGIC: u32 = 0x0060_6d4b;
+
+/// Version of the message format.
+pub(crate) const VERSION: u8 = 5;
+
+/// Size of the message header in bytes: magic, version, type, retry and body length.
+pub(crate) const HEADER_LEN: usize = 11;
+
+/// Upper bound on the body length accepted from munged.
+pub(crate) const MAX_BODY_LEN: u32 = 1 << 30;
+
+/// Type of a message.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MsgType {
+    EncodeRequest = 2,
+    EncodeResponse = 3,
+    DecodeRequest = 4,
+    DecodeResponse = 5,
+}
+
+/// An error raised when a message cannot be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MsgError(&'static str);
+
+impl fmt::Display for MsgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+/// Header preceding every message body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Header {
+    pub(crate) msg_type: u8,
+    pub(crate) retry: u8,
+    pub(crate) body_len: u32,
+}
+
+impl Header {
+    /// Parses a header and checks its magic number and version.
+    pub(crate) fn unpack(buf: &[u8; HEADER_LEN]) -> Result<Self, MsgError> {
+        let mut r = Reader(buf);
+
+        if r.u32()? != MAGIC {
+            return Err(MsgError("Received message with invalid magic"));
+        }
+        if r.u8()? != VERSION {
+            return Err(MsgError("Received message with unsupported version"));
+        }
+
+        let header = Header {
+            msg_type: r.u8()?,
+            retry: r.u8()?,
+            body_len: r.u32()?,
+        };
+
+        if header.body_len > MAX_BODY_LEN {
+            return Err(MsgError("Received message exceeding the maximum length"));
+        }
+        Ok(header)
+    }
+
+    /// Checks that the header announces a message of the expected type.
+    pub(crate) fn expect(&self, msg_type: MsgType) -> Result<(), MsgError> {
+        if self.msg_type == msg_type as u8 {
+            Ok(())
+        } else {
+            Err(MsgError("Received unexpected message type"))
+        }
+    }
+}
+
+/// Request asking munged to encode a credential.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct EncodeRequest {
+    pub(crate) cipher: u8,
+    pub(crate) mac: u8,
+    pub(crate) zip: u8,
+    pub(crate) realm: Vec<u8>,
+    pub(crate) ttl: u32,
+    pub(crate) auth_uid: u32,
+    pub(crate) auth_gid: u32,
+    pub(crate) data: Vec<u8>,
+}
+
+impl EncodeRequest {
+    /// Serializes the request including its header.
+    pub(crate) fn pack(&self, retry: u8) -> Result<Vec<u8>, MsgError> {
+        let mut w = Writer::new(MsgType::EncodeRequest, retry);
+        w.u8(self.cipher);
+        w.u8(self.mac);
+        w.u8(self.zip);
+        w.bytes8(&self.realm)?;
+        w.u32(self.ttl);
+        w.u32(self.auth_uid);
+        w.u32(self.auth_gid);
+        w.bytes32(&self.data)?;
+        w.finish()
+    }
+
+    /// Parses the body of an encode request.
+    #[cfg(test)]
+    pub(crate) fn unpack(body: &[u8]) -> Result<Self, MsgError> {
+        let mut r = Reader(body);
+        let req = EncodeRequest {
+            cipher: r.u8()?,
+            mac: r.u8()?,
+            zip: r.u8()?,
+            realm: r.bytes8()?,
+            ttl: r.u32()?,
+            auth_uid: r.u32()?,
+            auth_gid: r.u32()?,
+            data: r.bytes32()?,
+        };
+        r.finish()?;
+        Ok(req)
+    }
+}
+
+/// Response to an [`EncodeRequest`], carrying the credential string in `data`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct EncodeResponse {
+    pub(crate) error_num: u8,
+    pub(crate) error_str: Vec<u8>,
+    pub(crate) data: Vec<u8>,
+}
+
+impl EncodeResponse {
+    /// Serializes the response including its header.
+    #[cfg(test)]
+    pub(crate) fn pack(&self, retry: u8) -> Result<Vec<u8>, MsgError> {
+        let mut w = Writer::new(MsgType::EncodeResponse, retry);
+        w.u8(self.error_num);
+        w.bytes8(&self.error_str)?;
+        w.bytes32(&self.data)?;
+        w.finish()
+    }
+
+    /// Parses the body of an encode response.
+    pub(crate) fn unpack(body: &[u8]) -> Result<Self, MsgError> {
+        let mut r = Reader(body);
+        let rsp = EncodeResponse {
+            error_num: r.u8()?,
+            error_str: r.bytes8()?,
+            data: r.bytes32()?,
+        };
+        r.finish()?;
+        Ok(rsp)
+    }
+}
+
+/// Request asking munged to decode the credential string in `data`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct DecodeRequest {
+    pub(crate) data: Vec<u8>,
+}
+
+impl DecodeRequest {
+    /// Serializes the request including its header.
+    pub(crate) fn pack(&self, retry: u8) -> Result<Vec<u8>, MsgError> {
+        let mut w = Writer::new(MsgType::DecodeRequest, retry);
+        w.bytes32(&self.data)?;
+        w.finish()
+    }
+
+    /// Parses the body of a decode request.
+    #[cfg(test)]
+    pub(crate) fn unpack(body: &[u8]) -> Result<Self, MsgError> {
+        let mut r = Reader(body);
+        let req = DecodeRequest { data: r.bytes32()? };
+        r.finish()?;
+        Ok(req)
+    }
+}
+
+/// Response to a [`DecodeRequest`].
+///
+/// The credential metadata and payload are also filled in when the credential was
+/// rejected as expired, rewound or replayed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct DecodeResponse {
+    pub(crate) error_num: u8,
+    pub(crate) error_str: Vec<u8>,
+    pub(crate) cipher: u8,
+    pub(crate) mac: u8,
+    pub(crate) zip: u8,
+    pub(crate) realm: Vec<u8>,
+    pub(crate) ttl: u32,
+    pub(crate) addr: Vec<u8>,
+    pub(crate) encode_time: u32,
+    pub(crate) decode_time: u32,
+    pub(crate) cred_uid: u32,
+    pub(crate) cred_gid: u32,
+    pub(crate) auth_uid: u32,
+    pub(crate) auth_gid: u32,
+    pub(crate) data: Vec<u8>,
+}
+
+impl DecodeResponse {
+    /// Serializes the response including its header.
+    #[cfg(test)]
+    pub(crate) fn pack(&self, retry: u8) -> Result<Vec<u8>, MsgError> {
+        let mut w = Writer::new(MsgType::DecodeResponse, retry);
+        w.u8(self.error_num);
+        w.bytes8(&self.error_str)?;
+        w.u8(self.cipher);
+        w.u8(self.mac);
+        w.u8(self.zip);
+        w.bytes8(&self.realm)?;
+        w.u32(self.ttl);
+        w.bytes8(&self.addr)?;
+        w.u32(self.encode_time);
+        w.u32(self.decode_time);
+        w.u32(self.cred_uid);
+        w.u32(self.cred_gid);
+        w.u32(self.auth_uid);
+        w.u32(self.auth_gid);
+        w.bytes32(&self.data)?;
+        w.finish()
+    }
+
+    /// Parses the body of a decode response.
+    pub(crate) fn unpack(body: &[u8]) -> Result<Self, MsgError> {
+        let mut r = Reader(body);
+        let rsp = DecodeResponse {
+            error_num: r.u8()?,
+            error_str: r.bytes8()?,
+            cipher: r.u8()?,
+            mac: r.u8()?,
+            zip: r.u8()?,
+            realm: r.bytes8()?,
+            ttl: r.u32()?,
+            addr: r.bytes8()?,
+            encode_time: r.u32()?,
+            decode_time: r.u32()?,
+            cred_uid: r.u32()?,
+            cred_gid: r.u32()?,
+            auth_uid: r.u32()?,
+            auth_gid: r.u32()?,
+            data: r.bytes32()?,
+        };
+        r.finish()?;
+        Ok(rsp)
+    }
+}
+
+/// Serializes a message, filling in the header once the body is complete.
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn new(msg_type: MsgType, retry: u8) -> Self {
+        let mut buf = Vec::with_capacity(64);
+        buf.extend_from_slice(&MAGIC.to_be_bytes());
+        buf.push(VERSION);
+        buf.push(msg_type as u8);
+        buf.push(retry);
+        buf.extend_from_slice(&[0; 4]);
+        Writer(buf)
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn bytes8(&mut self, value: &[u8]) -> Result<(), MsgError> {
+        let len = u8::try_from(value.len()).map_err(|_| MsgError("Field exceeds 255 bytes"))?;
+        self.u8(len);
+        self.0.extend_from_slice(value);
+        Ok(())
+    }
+
+    fn bytes32(&mut self, value: &[u8]) -> Result<(), MsgError> {
+        let len = u32::try_from(value.len())
+            .ok()
+            .filter(|len| *len <= MAX_BODY_LEN)
+            .ok_or(MsgError("Message exceeds the maximum length"))?;
+        self.u32(len);
+        self.0.extend_from_slice(value);
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Vec<u8>, MsgError> {
+        let body_len = u32::try_from(self.0.len() - HEADER_LEN)
+            .map_err(|_| MsgError("Message exceeds the maximum length"))?;
+        self.0[7..HEADER_LEN].copy_from_slice(&body_len.to_be_bytes());
+        Ok(self.0)
+    }
+}
+
+/// Parses a message, failing on truncated input.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], MsgError> {
+        if self.0.len() < n {
+            return Err(MsgError("Received truncated message"));
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, MsgError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, MsgError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn bytes8(&mut self) -> Result<Vec<u8>, MsgError> {
+        let len = self.u8()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn bytes32(&mut self) -> Result<Vec<u8>, MsgError> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn finish(&self) -> Result<(), MsgError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(MsgError("Received message with trailing data"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod msg_tests {
+    use super::*;
+
+    fn body(packed: &[u8]) -> (Header, &[u8]) {
+        let header = Header::unpack(packed[..HEADER_LEN].try_into().unwrap()).unwrap();
+        (header, &packed[HEADER_LEN..])
+    }
+
+    #[test]
+    fn encode_request_roundtrip() {
+        let req = EncodeRequest {
+            cipher: 4,
+            mac: 5,
+            zip: 0,
+            realm: Vec::new(),
+            ttl: 300,
+            auth_uid: u32::MAX,
+            auth_gid: 100,
+            data: b"payload\0with nul".to_vec(),
+        };
+        let packed = req.pack(0).unwrap();
+        let (header, body) = body(&packed);
+
+        assert_eq!(header.expect(MsgType::EncodeRequest), Ok(()));
+        assert_eq!(header.body_len as usize, body.len());
+        assert_eq!(EncodeRequest::unpack(body).unwrap(), req);
+    }
+
+    #[test]
+    fn decode_response_roundtrip() {
+        let rsp = DecodeResponse {
+            error_num: 15,
+            error_str: b"Expired credential".to_vec(),
+            addr: vec![127, 0, 0, 1],
+            encode_time: 1_700_000_000,
+            decode_time: 1_700_000_100,
+            cred_uid: 1000,
+            cred_gid: 1000,
+            data: vec![0, 1, 2],
+            ..Default::default()
+        };
+        let packed = rsp.pack(1).unwrap();
+        let (header, body) = body(&packed);
+
+        assert_eq!(header.retry, 1);
+        assert_eq!(DecodeResponse::unpack(body).unwrap(), rsp);
+    }
+
+    #[test]
+    fn reject_malformed() {
+        let mut packed = DecodeRequest {
+            data: b"cred".to_vec(),
+        }
+        .pack(0)
+        .unwrap();
+        assert!(DecodeRequest::unpack(&packed[HEADER_LEN..packed.len() - 1]).is_err());
+
+        packed[0] ^= 0xff;
+        assert!(Header::unpack(packed[..HEADER_LEN].try_into().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    ffi::{self, CStr, CString},
+    ptr, slice,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use super::{Decoded, EncodeError, Metadata};
+use crate::{
+    credential::Credential,
+    enums::{MungeEnum, MungeOption},
+    ffi as c,
+};
+
+/// Owned pointer to a libmunge context.
+struct CtxPtr(*mut c::munge_ctx);
+
+// libmunge contexts are not thread-safe. A pointer is either owned by a single call or
+// only used behind the lock of a `RawCtx`, so it may move between threads but is never
+// used by two at once.
+unsafe impl Send for CtxPtr {}
+
+impl CtxPtr {
+    fn create() -> Option<Self> {
+        let ctx = unsafe { c::munge_ctx_create() };
+        (!ctx.is_null()).then_some(CtxPtr(ctx))
+    }
+
+    fn copy(&self) -> Option<Self> {
+        let ctx = unsafe { c::munge_ctx_copy(self.0) };
+        (!ctx.is_null()).then_some(CtxPtr(ctx))
+    }
+
+    fn get_i32(&self, option: MungeOption) -> Result<i32, u32> {
+        let mut value: i32 = 0;
+        match unsafe { c::munge_ctx_get(self.0, option as i32, &mut value) } {
+            0 => Ok(value),
+            err => Err(err),
+        }
+    }
+
+    fn get_u32(&self, option: MungeOption) -> Result<u32, u32> {
+        let mut value: u32 = 0;
+        match unsafe { c::munge_ctx_get(self.0, option as i32, &mut value) } {
+            0 => Ok(value),
+            err => Err(err),
+        }
+    }
+
+    fn get_time(&self, option: MungeOption) -> Result<i64, u32> {
+        let mut value: libc::time_t = 0;
+        match unsafe { c::munge_ctx_get(self.0, option as i32, &mut value) } {
+            0 => Ok(value),
+            err => Err(err),
+        }
+    }
+
+    fn strerror(&self) -> Option<CString> {
+        let err: *const ffi::c_char = unsafe { c::munge_ctx_strerror(self.0) };
+
+        if err.is_null() {
+            None
+        } else {
+            Some(unsafe { CStr::from_ptr(err) }.to_owned())
+        }
+    }
+
+    /// Reads the metadata libmunge recorded while decoding with this context.
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            cipher: self.get_i32(MungeOption::CipherType).unwrap_or_default(),
+            mac: self.get_i32(MungeOption::MacType).unwrap_or_default(),
+            zip: self.get_i32(MungeOption::ZipType).unwrap_or_default(),
+            ttl: self.get_i32(MungeOption::Ttl).unwrap_or_default(),
+            addr4: self
+                .get_u32(MungeOption::Addr4)
+                .unwrap_or_default()
+                .to_ne_bytes(),
+            encode_time: self.get_time(MungeOption::EncodeTime).unwrap_or_default(),
+            decode_time: self.get_time(MungeOption::DecodeTime).unwrap_or_default(),
+        }
+    }
+}
+
+impl Drop for CtxPtr {
+    fn drop(&mut self) {
+        unsafe { c::munge_ctx_destroy(self.0) };
+    }
+}
+
+/// Owned handle to a libmunge context.
+///
+/// Encode and decode run on a private copy of the context, which replaces it afterwards
+/// so the getters report the last call. The lock is only held while copying, reading
+/// or replacing the context, never during a call to munged.
+pub(crate) struct RawCtx(Mutex<CtxPtr>);
+
+impl RawCtx {
+    /// Creates a new libmunge context with default options, or `None` if libmunge
+    /// could not allocate it.
+    pub(crate) fn try_new() -> Option<Self> {
+        CtxPtr::create().map(|ctx| RawCtx(Mutex::new(ctx)))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CtxPtr> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Copies the context for a single call, or creates a default one if there is none.
+    fn copy_for_call(ctx: Option<&RawCtx>) -> Result<CtxPtr, u32> {
+        ctx.map_or_else(CtxPtr::create, |ctx| ctx.lock().copy())
+            .ok_or(c::munge_err_EMUNGE_NO_MEMORY)
+    }
+
+    /// Sets an integer option, returning the MUNGE error code.
+    pub(crate) fn set_u32(&self, option: MungeOption, value: u32) -> u32 {
+        unsafe { c::munge_ctx_set(self.lock().0, option as i32, value) }
+    }
+
+    /// Sets a string option, returning the MUNGE error code.
+    pub(crate) fn set_str(&self, option: MungeOption, value: &CStr) -> u32 {
+        unsafe { c::munge_ctx_set(self.lock().0, option as i32, value.as_ptr()) }
+    }
+
+    /// Retrieves a signed integer option.
+    pub(crate) fn get_i32(&self, option: MungeOption) -> Result<i32, u32> {
+        self.lock().get_i32(option)
+    }
+
+    /// Retrieves an unsigned integer option.
+    ///
+    /// For [`MungeOption::Addr4`] the value holds the address bytes in network order.
+    pub(crate) fn get_u32(&self, option: MungeOption) -> Result<u32, u32> {
+        self.lock().get_u32(option)
+    }
+
+    /// Retrieves a time option as seconds since the Unix epoch.
+    pub(crate) fn get_time(&self, option: MungeOption) -> Result<i64, u32> {
+        self.lock().get_time(option)
+    }
+
+    /// Retrieves a string option.
+    pub(crate) fn get_str(&self, option: MungeOption) -> Result<CString, u32> {
+        let ctx = self.lock();
+        let mut value: *const ffi::c_char = ptr::null();
+        match unsafe { c::munge_ctx_get(ctx.0, option as i32, &mut value) } {
+            0 if value.is_null() => Ok(CString::default()),
+            0 => Ok(unsafe { CStr::from_ptr(value) }.to_owned()),
+            err => Err(err),
+        }
+    }
+
+    /// Retrieves the description of the last error recorded in the context.
+    pub(crate) fn strerror(&self) -> Option<CString> {
+        self.lock().strerror()
+    }
+
+    /// Replaces the context by the copy a call ran on, like encode and decode do.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn replace(&self, call: RawCtx) {
+        *self.lock() = call.0.into_inner().unwrap_or_else(PoisonError::into_inner);
+    }
+}
+
+impl Clone for RawCtx {
+    fn clone(&self) -> Self {
+        RawCtx(Mutex::new(CtxPtr(unsafe {
+            c::munge_ctx_copy(self.lock().0)
+        })))
+    }
+}
+
+/// Retrieves the generic description of a MUNGE error code.
+pub(crate) fn strerror(err: u32) -> Option<&'static CStr> {
+    let err: *const ffi::c_char = unsafe { c::munge_strerror(err) };
+
+    if err.is_null() {
+        None
+    } else {
+        Some(unsafe { CStr::from_ptr(err) })
+    }
+}
+
+/// Checks whether libmunge supports the cipher, MAC or compression type.
+pub(crate) fn enum_is_valid(kind: MungeEnum, value: u32) -> bool {
+    unsafe { c::munge_enum_is_valid(kind as c::munge_enum_t, value as ffi::c_int) != 0 }
+}
+
+/// Retrieves the name of the cipher, MAC or compression type.
+pub(crate) fn enum_int_to_str(kind: MungeEnum, value: u32) -> Option<&'static CStr> {
+    let name = unsafe { c::munge_enum_int_to_str(kind as c::munge_enum_t, value as ffi::c_int) };
+
+    if name.is_null() {
+        None
+    } else {
+        Some(unsafe { CStr::from_ptr(name) })
+    }
+}
+
+/// Looks up a cipher, MAC or compression type by name or number.
+pub(crate) fn enum_str_to_int(kind: MungeEnum, name: &CStr) -> Option<u32> {
+    let value = unsafe { c::munge_enum_str_to_int(kind as c::munge_enum_t, name.as_ptr()) };
+
+    u32::try_from(value).ok()
+}
+
+/// Encodes the payload into a credential string.
+///
+/// The payload length must already be known to fit into a `c_int`.
+pub(crate) fn encode(ctx: Option<&RawCtx>, payload: &[u8]) -> Result<CString, EncodeError> {
+    let call = RawCtx::copy_for_call(ctx).map_err(|err| (err, None))?;
+    let mut cred: *mut ffi::c_char = ptr::null_mut();
+    let buf: *const ffi::c_void = payload.as_ptr() as *const ffi::c_void;
+
+    let err: u32 = unsafe { c::munge_encode(&mut cred, call.0, buf, payload.len() as ffi::c_int) };
+    let description = call.strerror();
+    if let Some(ctx) = ctx {
+        *ctx.lock() = call;
+    }
+
+    if err != 0 {
+        return Err((err, description));
+    }
+
+    let resp = unsafe { CStr::from_ptr(cred) }.to_owned();
+    unsafe { libc::free(cred as *mut ffi::c_void) };
+    Ok(resp)
+}
+
+/// Decodes the credential string, returning the MUNGE error code along with whatever
+/// libmunge managed to decode.
+pub(crate) fn decode(cred: &CStr, ctx: Option<&RawCtx>) -> Decoded {
+    let call = match RawCtx::copy_for_call(ctx) {
+        Ok(call) => call,
+        Err(err) => {
+            return Decoded {
+                err,
+                ..Decoded::default()
+            }
+        }
+    };
+    let mut dmsg: *mut ffi::c_void = ptr::null_mut();
+    let mut len: ffi::c_int = 0;
+    let mut uid: c::uid_t = 0;
+    let mut gid: c::gid_t = 0;
+
+    let err: u32 = unsafe {
+        c::munge_decode(
+            cred.as_ptr(),
+            call.0,
+            &mut dmsg,
+            &mut len,
+            &mut uid,
+            &mut gid,
+        )
+    };
+
+    // libmunge may hand out a payload even when the credential is rejected,
+    // so the buffer has to be released on every path.
+    let payload: Vec<u8> = if !dmsg.is_null() && len > 0 {
+        unsafe { slice::from_raw_parts(dmsg as *const u8, len as usize) }.to_vec()
+    } else {
+        Vec::new()
+    };
+    unsafe { libc::free(dmsg) };
+
+    let decoded = Decoded {
+        err,
+        description: call.strerror(),
+        cred: Credential {
+            message: payload,
+            uid,
+            gid,
+        },
+        metadata: call.metadata(),
+    };
+    if let Some(ctx) = ctx {
+        *ctx.lock() = call;
+    }
+    decoded
+}
@@ -0,0 +1,58 @@
+//! Low-level access to MUNGE.
+//!
+//! By default the calls go through the system `libmunge`. With the `pure-rust` feature
+//! the munged socket protocol is spoken directly instead, so neither libmunge nor its
+//! headers are required at build time. Both implementations expose the same functions
+//! with libmunge semantics: error codes are raw `munge_err_t` values and the context
+//! records the description and metadata of the last call.
+//!
+//! Each call also returns its own error description and metadata, so callers sharing
+//! a context between threads never read back the outcome of another call.
+
+use std::ffi::CString;
+
+use crate::credential::Credential;
+
+#[cfg(feature = "pure-rust")]
+mod client;
+#[cfg(not(feature = "pure-rust"))]
+mod libmunge;
+
+#[cfg(feature = "pure-rust")]
+pub(crate) use client::{
+    decode, encode, enum_int_to_str, enum_is_valid, enum_str_to_int, strerror, RawCtx,
+};
+#[cfg(all(feature = "pure-rust", feature = "tokio"))]
+pub(crate) use client::{decode_async, encode_async};
+#[cfg(not(feature = "pure-rust"))]
+pub(crate) use libmunge::{
+    decode, encode, enum_int_to_str, enum_is_valid, enum_str_to_int, strerror, RawCtx,
+};
+
+/// Metadata munged reports about a decoded credential.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Metadata {
+    pub(crate) cipher: i32,
+    pub(crate) mac: i32,
+    pub(crate) zip: i32,
+    pub(crate) ttl: i32,
+    /// Address bytes in network order.
+    pub(crate) addr4: [u8; 4],
+    pub(crate) encode_time: i64,
+    pub(crate) decode_time: i64,
+}
+
+/// Outcome of a single decode.
+#[derive(Debug, Default)]
+pub(crate) struct Decoded {
+    /// MUNGE error code, 0 on success.
+    pub(crate) err: u32,
+    /// Description of the error, if any.
+    pub(crate) description: Option<CString>,
+    /// Whatever could be decoded, also for rejected credentials.
+    pub(crate) cred: Credential<Vec<u8>>,
+    pub(crate) metadata: Metadata,
+}
+
+/// Error code and description of a failed encode.
+pub(crate) type EncodeError = (u32, Option<CString>);
@@ -0,0 +1,149 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    credential::Credential,
+    ctx::Context,
+    enums::Error,
+    munge::{decode_bytes, encode_bytes},
+};
+
+/// Serialization format of typed payloads.
+///
+/// Both sides have to agree on the format, it is not recorded in the credential.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadFormat {
+    /// JSON, readable by any MUNGE client, eg. `unmunge`.
+    #[default]
+    Json,
+    /// Compact binary encoding using bincode.
+    Bincode,
+}
+
+impl PayloadFormat {
+    fn serialize<T: Serialize + ?Sized>(self, value: &T) -> Result<Vec<u8>, Error> {
+        match self {
+            PayloadFormat::Json => {
+                serde_json::to_vec(value).map_err(|e| Error::Serialization(e.into()))
+            }
+            PayloadFormat::Bincode => {
+                bincode::serialize(value).map_err(|e| Error::Serialization(e))
+            }
+        }
+    }
+
+    fn deserialize<T: DeserializeOwned>(self, payload: &[u8]) -> Result<T, Error> {
+        match self {
+            PayloadFormat::Json => {
+                serde_json::from_slice(payload).map_err(|e| Error::Serialization(e.into()))
+            }
+            PayloadFormat::Bincode => {
+                bincode::deserialize(payload).map_err(|e| Error::Serialization(e))
+            }
+        }
+    }
+}
+
+/// Serializes the given value and encodes it into a base64 encoded credential string.
+///
+/// # Arguments
+///
+/// * `value` - The value to be included with the encoded credential.
+/// * `format` - The format the value is serialized with.
+/// * `ctx` - An optional reference to a [`Context`]. If no context is provided, defaults will be used.
+///
+/// # Errors
+///
+/// Returns [`Error::Serialization`] if the value cannot be serialized, or the errors of
+/// [`crate::encode_bytes`].
+///
+/// # Example
+///
+/// ```ignore
+/// #[derive(Serialize)]
+/// struct Job { id: u64, queue: String }
+///
+/// let cred = encode_value(&Job { id: 42, queue: "gpu".into() }, PayloadFormat::Json, None)?;
+/// ```
+pub fn encode_value<T: Serialize + ?Sized>(
+    value: &T,
+    format: PayloadFormat,
+    ctx: Option<&Context>,
+) -> Result<String, Error> {
+    encode_bytes(&format.serialize(value)?, ctx)
+}
+
+/// Decodes the provided base64 encoded string and deserializes its payload.
+///
+/// # Arguments
+///
+/// * `encoded_msg` - The base64 encoded credential string to decode.
+/// * `format` - The format the payload was serialized with.
+/// * `ctx` - An optional reference to a [`Context`]. If no context is provided, defaults will be used.
+///
+/// # Errors
+///
+/// Returns the errors of [`crate::decode_bytes`], or [`Error::Serialization`] if the
+/// payload cannot be deserialized into `T`.
+///
+/// # Example
+///
+/// ```ignore
+/// let cred: Credential<Job> = decode_value(&encoded, PayloadFormat::Json, None)?;
+/// println!("Job {} submitted by UID {}", cred.message.id, cred.uid);
+/// ```
+pub fn decode_value<T: DeserializeOwned>(
+    encoded_msg: &str,
+    format: PayloadFormat,
+    ctx: Option<&Context>,
+) -> Result<Credential<T>, Error> {
+    let cred = decode_bytes(encoded_msg, ctx)?;
+
+    Ok(Credential {
+        uid: cred.uid,
+        gid: cred.gid,
+        message: format.deserialize(&cred.message)?,
+    })
+}
+
+#[cfg(test)]
+mod value_tests {
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        enums::Error,
+        munge::encode,
+        value::{decode_value, encode_value, PayloadFormat},
+    };
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Job {
+        id: u64,
+        queue: String,
+        nodes: Vec<u16>,
+    }
+
+    #[test]
+    fn encode_decode_value() {
+        let job = Job {
+            id: 42,
+            queue: "gpu".to_string(),
+            nodes: vec![1, 2, 3],
+        };
+
+        for format in [PayloadFormat::Json, PayloadFormat::Bincode] {
+            let cred = encode_value(&job, format, None).expect("Failed to encode");
+            let res = decode_value::<Job>(&cred, format, None).expect("Failed to decode");
+
+            assert_eq!(res.message, job);
+            assert_eq!(res.uid, unsafe { libc::getuid() });
+        }
+    }
+
+    #[test]
+    fn decode_value_mismatch() {
+        let cred = encode("not a job", None).expect("Failed to encode");
+
+        let res = decode_value::<Job>(&cred, PayloadFormat::Json, None);
+        assert!(matches!(res, Err(Error::Serialization(_))));
+    }
+}